@@ -0,0 +1,376 @@
+//! Benchmarks of the bitboard `Board` against a plain array of tiles, which is how the board used to be stored.
+//! The array board keeps track of everything `Board` does in the same way, just reading the tiles one by one, so
+//! that both do the same work and it also checks that both agree.
+//!
+//! The bitboard is faster at reading lines of tiles, which is what scoring a position does. Reading a single tile
+//! is a few shifts instead of a load, so `get` on its own is slower than on the array.
+
+use crate::board::{Board, Move, MoveFilter, Player, Tile, CANDIDATE_RADIUS, DIRECTIONS};
+use crate::pattern::{PatternTable, ShapeCounts, SHAPE_COUNT};
+use crate::random::Rng;
+use crate::rules::Rule;
+use crate::zobrist;
+use glam::{ivec2, IVec2};
+use std::convert::TryFrom;
+use std::hint::black_box;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The tiles of a board, stored row by row.
+pub struct ArrayBoard {
+    grid: Vec<Tile>,
+    nearby: Vec<u8>,
+    width: usize,
+    height: usize,
+    win_length: i32,
+    rule: Rule,
+    pub score: i32,
+    pub player_a_one_left: i32,
+    pub player_b_one_left: i32,
+    pub won: Option<Player>,
+    patterns: Option<[Arc<PatternTable>; 2]>,
+    pub shapes: [ShapeCounts; 2],
+    pub hash: u64,
+}
+
+impl ArrayBoard {
+    pub fn new(width: usize, height: usize, win_length: i32, rule: Rule) -> Self {
+        let patterns = PatternTable::get(win_length, rule, Player::A)
+            .and_then(|a| Some([a, PatternTable::get(win_length, rule, Player::B)?]));
+        Self {
+            grid: vec![None; width * height],
+            nearby: vec![0; width * height],
+            width,
+            height,
+            win_length,
+            rule,
+            score: 0,
+            player_a_one_left: 0,
+            player_b_one_left: 0,
+            won: None,
+            patterns,
+            shapes: [[0; SHAPE_COUNT]; 2],
+            hash: 0,
+        }
+    }
+
+    pub fn get(&self, pos: IVec2) -> Option<Tile> {
+        let x = usize::try_from(pos.x).ok()?;
+        let y = usize::try_from(pos.y).ok()?;
+        if x < self.width && y < self.height {
+            Some(self.grid[y * self.width + x])
+        } else {
+            None
+        }
+    }
+
+    /// Places a stone and updates everything like `Board::do_move`, returning the winner.
+    pub fn place(&mut self, r#move: Move) -> Option<Player> {
+        let pos = r#move.pos;
+        let (score, a_one_left, b_one_left, _) = self.score_for_position(pos);
+        self.score -= score;
+        self.player_a_one_left -= a_one_left;
+        self.player_b_one_left -= b_one_left;
+        let before = self.shapes_around(pos);
+        self.grid[pos.y as usize * self.width + pos.x as usize] = Some(r#move.player);
+        for y in pos.y - CANDIDATE_RADIUS..=pos.y + CANDIDATE_RADIUS {
+            for x in pos.x - CANDIDATE_RADIUS..=pos.x + CANDIDATE_RADIUS {
+                if self.get(ivec2(x, y)).is_some() {
+                    self.nearby[y as usize * self.width + x as usize] += 1;
+                }
+            }
+        }
+        let (score, a_one_left, b_one_left, winner) = self.score_for_position(pos);
+        self.score += score;
+        self.player_a_one_left += a_one_left;
+        self.player_b_one_left += b_one_left;
+        let after = self.shapes_around(pos);
+        for player in 0..2 {
+            for shape in 0..SHAPE_COUNT {
+                self.shapes[player][shape] += after[player][shape] - before[player][shape];
+            }
+        }
+
+        self.hash ^= zobrist::tile_key(pos, r#move.player) ^ zobrist::SIDE_KEY;
+        self.won = self.won.or(winner);
+        winner
+    }
+
+    /// The empty tiles within `CANDIDATE_RADIUS` of a stone, like `MoveFilter::Nearby` gives them.
+    pub fn nearby_tiles(&self) -> impl Iterator<Item = IVec2> + '_ {
+        (0..self.height as i32)
+            .flat_map(move |y| (0..self.width as i32).map(move |x| ivec2(x, y)))
+            .filter(move |&pos| {
+                let index = pos.y as usize * self.width + pos.x as usize;
+                self.grid[index].is_none() && self.nearby[index] > 0
+            })
+    }
+
+    /// The shapes of the stones whose patterns include `pos`, like `Board` counts them.
+    fn shapes_around(&self, pos: IVec2) -> [ShapeCounts; 2] {
+        let mut counts = [[0; SHAPE_COUNT]; 2];
+        let tables = match &self.patterns {
+            Some(tables) => tables,
+            None => return counts,
+        };
+
+        let reach = self.win_length;
+        for &direction in &DIRECTIONS {
+            for i in -reach..=reach {
+                let stone = pos + direction * i;
+                let player = match self.get(stone) {
+                    Some(Some(player)) => player,
+                    _ => continue,
+                };
+
+                let mut tiles = [0; 3];
+                for bit in 0..=2 * reach {
+                    let tile = match self.get(stone + direction * (bit - reach)) {
+                        Some(Some(owner)) if owner == player => 0,
+                        Some(Some(_)) => 1,
+                        Some(None) => continue,
+                        None => 2,
+                    };
+                    tiles[tile] |= 1 << bit;
+                }
+                if let Some(shape) = tables[player as usize].shape(tiles[0], tiles[1], tiles[2]) {
+                    counts[player as usize][shape as usize] += 1;
+                }
+            }
+        }
+        counts
+    }
+
+    pub fn score_for_position(&self, pos: IVec2) -> (i32, i32, i32, Option<Player>) {
+        let mut total = (0, 0, 0);
+        for &direction in &DIRECTIONS {
+            let (score, a_one_left, b_one_left) = self.directional_score(pos, direction);
+            total = (total.0 + score, total.1 + a_one_left, total.2 + b_one_left);
+        }
+
+        let winner = match self.get(pos) {
+            Some(Some(player)) => DIRECTIONS
+                .iter()
+                .any(|&direction| {
+                    let (length, ends) = self.run(pos, direction, player);
+                    self.rule.is_winning_line(player, length, ends, self.win_length)
+                })
+                .then_some(player),
+            _ => None,
+        };
+
+        (total.0, total.1, total.2, winner)
+    }
+
+    fn run(&self, pos: IVec2, direction: IVec2, player: Player) -> (i32, [Tile; 2]) {
+        let mut length = 1;
+        let mut ends = [None; 2];
+        for (end, &sign) in ends.iter_mut().zip(&[1, -1]) {
+            let mut current = pos + direction * sign;
+            while self.get(current) == Some(Some(player)) {
+                length += 1;
+                current += direction * sign;
+            }
+            *end = self.get(current).flatten();
+        }
+        (length, ends)
+    }
+
+    fn directional_score(&self, pos: IVec2, direction: IVec2) -> (i32, i32, i32) {
+        let mut score = 0;
+        let mut player_a_one_left = 0;
+        let mut player_b_one_left = 0;
+        let win_length = self.win_length;
+
+        for start in -win_length..=1 {
+            let start = pos + direction * start;
+            if self.get(start).is_none() || self.get(start + direction * (win_length - 1)).is_none() {
+                continue;
+            }
+
+            let mut player_a = 0;
+            let mut player_b = 0;
+            for i in 0..win_length {
+                match self.get(start + direction * i) {
+                    Some(Some(Player::A)) => player_a += 1,
+                    Some(Some(Player::B)) => player_b += 1,
+                    _ => {}
+                }
+            }
+
+            let before = self.get(start - direction).flatten();
+            let after = self.get(start + direction * win_length).flatten();
+
+            if player_a == 0 && player_b < win_length {
+                if !self.rule.can_win_window(Player::B, before, after) {
+                    continue;
+                }
+                if player_b == win_length - 1 {
+                    player_b_one_left += 1;
+                }
+                score -= player_b * player_b;
+            } else if player_b == 0 && player_a < win_length {
+                if !self.rule.can_win_window(Player::A, before, after) {
+                    continue;
+                }
+                if player_a == win_length - 1 {
+                    player_a_one_left += 1;
+                }
+                score += player_a * player_a;
+            }
+        }
+
+        (score, player_a_one_left, player_b_one_left)
+    }
+}
+
+/// Random games on `board`, that stop when someone wins or after `length` moves.
+fn random_games(board: &Board, count: usize, length: usize, rng: &Rng) -> Vec<Vec<Move>> {
+    (0..count)
+        .map(|_| {
+            let mut board = board.clone();
+            let mut moves = Vec::new();
+            while moves.len() < length && board.won.is_none() {
+                let valid: Vec<Move> = board.get_moves().collect();
+                if valid.is_empty() {
+                    break;
+                }
+                let r#move = valid[rng.below(valid.len() as u64) as usize];
+                board.do_move(r#move);
+                moves.push(r#move);
+            }
+            moves
+        })
+        .collect()
+}
+
+fn array_board(board: &Board) -> ArrayBoard {
+    ArrayBoard::new(board.width(), board.height(), board.win_length(), board.rule())
+}
+
+fn time(f: impl FnOnce()) -> Duration {
+    let start = Instant::now();
+    f();
+    start.elapsed()
+}
+
+fn report(name: &str, bitboard: Duration, array: Duration) {
+    println!(
+        "{:<20} bitboard {:>10.2?}  array {:>10.2?}  {:.2}x",
+        name,
+        bitboard,
+        array,
+        array.as_secs_f64() / bitboard.as_secs_f64().max(f64::EPSILON),
+    );
+}
+
+/// Plays the same random games on both boards, and prints how long the common operations take on each.
+pub fn run(board: &Board, games: usize, seed: u64) {
+    let rng = Rng::new(seed);
+    let games = random_games(board, games, board.width() * board.height() / 2, &rng);
+
+    let mut boards = Vec::with_capacity(games.len());
+    let mut arrays = Vec::with_capacity(games.len());
+    let bitboard = time(|| {
+        for game in &games {
+            let mut board = board.clone();
+            for &r#move in game {
+                board.do_move(r#move);
+            }
+            boards.push(board);
+        }
+    });
+    let array = time(|| {
+        for game in &games {
+            let mut array = array_board(board);
+            for &r#move in game {
+                array.place(r#move);
+            }
+            arrays.push(array);
+        }
+    });
+    report("play games", bitboard, array);
+
+    let positions: Vec<IVec2> = board.positions().collect();
+    let bitboard = time(|| {
+        for board in &boards {
+            for &pos in &positions {
+                black_box(board.score_for_position(pos));
+            }
+        }
+    });
+    let array = time(|| {
+        for array in &arrays {
+            for &pos in &positions {
+                black_box(array.score_for_position(pos));
+            }
+        }
+    });
+    report("score_for_position", bitboard, array);
+
+    // In renju the board also checks every move for being forbidden, which the array doesn't.
+    if !board.rule().has_forbidden_moves(Player::A) {
+        let bitboard = time(|| {
+            for board in &boards {
+                black_box(board.get_moves_filtered(MoveFilter::Nearby).count());
+            }
+        });
+        let array = time(|| {
+            for array in &arrays {
+                black_box(array.nearby_tiles().count());
+            }
+        });
+        report("nearby moves", bitboard, array);
+    }
+
+    let array = time(|| {
+        for array in &arrays {
+            for &pos in &positions {
+                black_box(array.get(pos));
+            }
+        }
+    });
+    let bitboard = time(|| {
+        for board in &boards {
+            for &pos in &positions {
+                black_box(board.get(pos));
+            }
+        }
+    });
+    report("get", bitboard, array);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn agrees_with_the_array_board() {
+        let rng = Rng::new(7);
+        for rule in [Rule::Freestyle, Rule::Renju, Rule::Standard, Rule::Caro] {
+            for win_length in [4, 5] {
+                let board = Board::new(9, 9, win_length).with_rule(rule);
+                for game in random_games(&board, 4, 81, &rng) {
+                    let mut board = board.clone();
+                    let mut array = array_board(&board);
+                    for r#move in game {
+                        board.do_move(r#move);
+                        array.place(r#move);
+
+                        assert_eq!(
+                            (board.score, board.player_a_one_left, board.player_b_one_left, board.won, board.hash),
+                            (array.score, array.player_a_one_left, array.player_b_one_left, array.won, array.hash),
+                            "{}",
+                            board.to_notation(),
+                        );
+                        assert_eq!([board.shape_counts(Player::A), board.shape_counts(Player::B)], array.shapes);
+                        let nearby: Vec<IVec2> =
+                            board.get_moves_filtered(MoveFilter::Nearby).map(|r#move| r#move.pos).collect();
+                        let allowed = |&pos: &IVec2| !board.is_forbidden(pos, board.current_player);
+                        assert_eq!(nearby, array.nearby_tiles().filter(allowed).collect::<Vec<_>>(), "{}", board.to_notation());
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//! The stones of a board as bitsets, one for every row, column and diagonal, so that the stones along a line can
+//! be read with a couple of shifts instead of looking at the tiles one by one.
+
+use crate::board::{Player, Tile};
+use glam::IVec2;
+
+/// The longest line a bitset can hold.
+pub const MAX_LINE_LENGTH: usize = 64;
+
+/// The stones of both players, as bitsets of the stones of `Player::A` and `Player::B` along every line. The
+/// positions given to it have to be on the board.
+#[derive(Clone)]
+pub struct Bitboard {
+    width: usize,
+    height: usize,
+    /// Every row, bit `x` of row `y` is the stone at `(x, y)`.
+    rows: Vec<[u64; 2]>,
+    /// Every column, bit `y` of column `x` is the stone at `(x, y)`.
+    columns: Vec<[u64; 2]>,
+    /// The diagonals going down and to the right, indexed by `x - y + height - 1`, the bit is `y`.
+    diagonals: Vec<[u64; 2]>,
+    /// The diagonals going down and to the left, indexed by `x + y`, the bit is `y`.
+    anti_diagonals: Vec<[u64; 2]>,
+}
+
+impl Bitboard {
+    pub fn new(width: usize, height: usize) -> Self {
+        assert!(
+            width <= MAX_LINE_LENGTH && height <= MAX_LINE_LENGTH,
+            "Cannot handle a board greater than {} in size",
+            MAX_LINE_LENGTH,
+        );
+
+        Self {
+            width,
+            height,
+            rows: vec![[0; 2]; height],
+            columns: vec![[0; 2]; width],
+            diagonals: vec![[0; 2]; width + height - 1],
+            anti_diagonals: vec![[0; 2]; width + height - 1],
+        }
+    }
+
+    #[inline]
+    pub fn get(&self, pos: IVec2) -> Tile {
+        // Looking the tile up instead of branching on the bits is faster, because the branches are hard to predict.
+        const TILES: [Tile; 3] = [None, Some(Player::A), Some(Player::B)];
+        let row = self.rows[pos.y as usize];
+        TILES[((row[0] >> pos.x & 1) | (row[1] >> pos.x & 1) << 1) as usize]
+    }
+
+    pub fn set(&mut self, pos: IVec2, tile: Tile) {
+        let (x, y) = (pos.x as usize, pos.y as usize);
+        let diagonal = x + self.height - 1 - y;
+        let anti_diagonal = x + y;
+
+        for player in [Player::A, Player::B] {
+            let i = player as usize;
+            if tile == Some(player) {
+                self.rows[y][i] |= 1 << x;
+                self.columns[x][i] |= 1 << y;
+                self.diagonals[diagonal][i] |= 1 << y;
+                self.anti_diagonals[anti_diagonal][i] |= 1 << y;
+            } else {
+                self.rows[y][i] &= !(1 << x);
+                self.columns[x][i] &= !(1 << y);
+                self.diagonals[diagonal][i] &= !(1 << y);
+                self.anti_diagonals[anti_diagonal][i] &= !(1 << y);
+            }
+        }
+    }
+
+    /// The empty tiles of row `y`, bit `x` being the tile at `(x, y)`.
+    pub fn empty_in_row(&self, y: usize) -> u64 {
+        !(self.rows[y][0] | self.rows[y][1]) & low_bits(self.width as u32)
+    }
+
+    /// The line through `pos` along one of `board::DIRECTIONS`. Returns the stones of both players and the tiles
+    /// that are on the board, and the bit of `pos` in them. Going one step along `direction` is going one bit up.
+    fn line(&self, pos: IVec2, direction: IVec2) -> ([u64; 2], u64, i32) {
+        let (x, y) = (pos.x as usize, pos.y as usize);
+        let (width, height) = (self.width as i32, self.height as i32);
+
+        match (direction.x, direction.y) {
+            (1, 0) => (self.rows[y], low_bits(width as u32), pos.x),
+            (0, 1) => (self.columns[x], low_bits(height as u32), pos.y),
+            (1, 1) => {
+                // The tiles where `x - y` is the same as for `pos`.
+                let offset = pos.x - pos.y;
+                let first = (-offset).max(0);
+                let last = (width - 1 - offset).min(height - 1);
+                let index = x + self.height - 1 - y;
+                (
+                    self.diagonals[index],
+                    low_bits((last + 1) as u32) & !low_bits(first as u32),
+                    pos.y,
+                )
+            }
+            (-1, 1) => {
+                // The tiles where `x + y` is the same as for `pos`.
+                let sum = pos.x + pos.y;
+                let first = (sum - width + 1).max(0);
+                let last = sum.min(height - 1);
+                let index = x + y;
+                (
+                    self.anti_diagonals[index],
+                    low_bits((last + 1) as u32) & !low_bits(first as u32),
+                    pos.y,
+                )
+            }
+            _ => panic!("Not one of the four directions"),
+        }
+    }
+
+    /// The `length` tiles starting `from` steps away from `pos` along `direction`, as the stones of both players
+    /// and the tiles that are on the board. Bit `i` is the tile `from + i` steps away. `length` can be at most 64.
+    pub fn window(&self, pos: IVec2, direction: IVec2, from: i32, length: u32) -> ([u64; 2], u64) {
+        let (stones, on_board, bit) = self.line(pos, direction);
+        let start = bit + from;
+        let mask = low_bits(length);
+        (
+            [shift_down(stones[0], start) & mask, shift_down(stones[1], start) & mask],
+            shift_down(on_board, start) & mask,
+        )
+    }
+
+    /// The number of stones of `player` in an unbroken line from `pos` along `direction`, not counting `pos`.
+    pub fn run(&self, pos: IVec2, direction: IVec2, player: Player) -> i32 {
+        let (stones, _, bit) = self.line(pos, direction);
+        let own = stones[player as usize];
+        shift_down(own, bit + 1).trailing_ones() as i32
+    }
+
+    /// Like `run`, but going against `direction`.
+    pub fn run_back(&self, pos: IVec2, direction: IVec2, player: Player) -> i32 {
+        let (stones, _, bit) = self.line(pos, direction);
+        let own = stones[player as usize];
+        if bit == 0 {
+            0
+        } else {
+            (own << (64 - bit)).leading_ones() as i32
+        }
+    }
+}
+
+/// A mask of the lowest `count` bits.
+fn low_bits(count: u32) -> u64 {
+    if count >= 64 {
+        u64::MAX
+    } else {
+        (1 << count) - 1
+    }
+}
+
+/// Shifts `bits` down by `shift`, or up if it's negative, with the bits that are shifted out being lost.
+fn shift_down(bits: u64, shift: i32) -> u64 {
+    match shift {
+        0..=63 => bits >> shift,
+        -63..=-1 => bits << -shift,
+        _ => 0,
+    }
+}
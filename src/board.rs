@@ -1,9 +1,32 @@
 use std::convert::TryFrom;
-use crate::ScoringFunction;
-use glam::{IVec2, ivec2};
+use std::error::Error;
+use std::fmt;
+use crate::bitboard::{Bitboard, MAX_LINE_LENGTH};
+use crate::opening::Opening;
+use crate::pattern::{PatternTable, ShapeCounts, SHAPE_COUNT};
+use crate::rules::{self, LineCell, Rule};
+use crate::zobrist;
+use glam::{IVec2, ivec2, const_ivec2};
+use std::sync::Arc;
 
-pub const WORLD_SIZE: usize = 16;
-pub const WIN_LENGTH: i32 = 5;
+/// The size of the board created by `Board::default`.
+pub const DEFAULT_WORLD_SIZE: usize = 16;
+/// The number of stones in a row needed to win on a board created by `Board::default`.
+pub const DEFAULT_WIN_LENGTH: i32 = 5;
+/// The longest win length a board can have. The score looks at the windows around a stone as one bitset.
+pub const MAX_WIN_LENGTH: i32 = (MAX_LINE_LENGTH as i32 - 3) / 2;
+
+/// How close to a stone a tile has to be to count as a candidate for `MoveFilter::Nearby`. The tiles are the
+/// square around the stone, up to this many tiles away both across and down.
+pub const CANDIDATE_RADIUS: i32 = 2;
+
+/// The four directions a line can go in.
+pub const DIRECTIONS: [IVec2; 4] = [
+    const_ivec2!([0, 1]),
+    const_ivec2!([1, 1]),
+    const_ivec2!([-1, 1]),
+    const_ivec2!([1, 0]),
+];
 
 pub struct BoardHandle<'a> {
     pub board: &'a mut Board,
@@ -11,6 +34,8 @@ pub struct BoardHandle<'a> {
     score: i32,
     player_a_one_left: i32,
     player_b_one_left: i32,
+    shapes: [ShapeCounts; 2],
+    hash: u64,
 }
 
 impl Drop for BoardHandle<'_> {
@@ -20,20 +45,40 @@ impl Drop for BoardHandle<'_> {
         self.board.score = self.score;
         self.board.player_a_one_left = self.player_a_one_left;
         self.board.player_b_one_left = self.player_b_one_left;
+        self.board.shapes = self.shapes;
+        self.board.hash = self.hash;
         self.board.current_player = self.board.current_player.rotate();
         self.board.moves -= 1;
+        self.board.history.pop();
     }
 }
 
-#[derive(Default, Clone, Copy)]
+#[derive(Clone)]
 pub struct Board {
-    grid: [[Tile; WORLD_SIZE]; WORLD_SIZE],
+    stones: Bitboard,
+    /// For every tile, the number of stones within `CANDIDATE_RADIUS` of it, including the tile itself.
+    nearby: Vec<u8>,
+    width: usize,
+    height: usize,
+    win_length: i32,
+    rule: Rule,
+    opening: Opening,
     pub current_player: Player,
     pub moves: usize,
     pub score: i32,
     pub won: Option<Player>,
     pub player_a_one_left: i32,
     pub player_b_one_left: i32,
+    /// The pattern tables of both players, if the win length isn't too long for them.
+    patterns: Option<[Arc<PatternTable>; 2]>,
+    /// How many stones of each player are part of each shape.
+    shapes: [ShapeCounts; 2],
+    /// The zobrist hash of the stones on the board and the player to move.
+    pub hash: u64,
+    /// The moves made on this board, oldest first. Stones that were on the board when it was set up aren't in here.
+    history: Vec<Move>,
+    /// Moves that were undone, the next one to redo is last.
+    redo_stack: Vec<Move>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -43,37 +88,171 @@ pub struct Move {
 }
 
 impl Move {
-    pub fn from_string(current_player: Player, string: &str) -> Option<Self> {
-        let mut chars = string.trim().chars();
-        let x = chars.next()?.to_digit(36)? as i32;
-        let y = chars.next()?.to_digit(36)? as i32;
-        let pos = ivec2(x, y);
+    /// Reads a move written as two base 36 digits, the column and then the row, like `7a`.
+    pub fn from_string(current_player: Player, string: &str) -> Result<Self, MoveError> {
+        Self::from_string_with_style(current_player, string, CoordStyle::Base36, 0)
+    }
 
-        if chars.next().is_some() {
-            return None;
+    /// Reads a move written in the given style. Algebraic rows count from the bottom, so they need the height of
+    /// the board.
+    pub fn from_string_with_style(current_player: Player, string: &str, style: CoordStyle, height: usize) -> Result<Self, MoveError> {
+        match style.parse(string, height) {
+            Some(pos) => Ok(Self { pos, player: current_player }),
+            None => Err(MoveError::Malformed(string.trim().to_string())),
         }
+    }
+}
 
-        Some(Self {
-            pos,
-            player: current_player,
-        })
+/// Why a move can't be done.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MoveError {
+    /// The text of the move couldn't be read.
+    Malformed(String),
+    OutOfBounds(IVec2),
+    Occupied(IVec2),
+    /// Someone has already won.
+    GameOver,
+    /// It's the turn of the other player.
+    WrongPlayer(Player),
+    /// The rule doesn't allow the player to move here, like a double three in renju.
+    Forbidden(IVec2),
+    /// The opening doesn't allow a move here yet.
+    RestrictedByOpening(IVec2),
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Malformed(text) => write!(fmt, "{:?} is not a move", text),
+            Self::OutOfBounds(pos) => write!(fmt, "{},{} is outside of the board", pos.x, pos.y),
+            Self::Occupied(pos) => write!(fmt, "there is already a stone at {},{}", pos.x, pos.y),
+            Self::GameOver => write!(fmt, "the game is over"),
+            Self::WrongPlayer(player) => write!(fmt, "it is not the turn of {:?}", player),
+            Self::Forbidden(pos) => write!(fmt, "the rule forbids moving at {},{}", pos.x, pos.y),
+            Self::RestrictedByOpening(pos) => write!(fmt, "the opening doesn't allow moving at {},{} yet", pos.x, pos.y),
+        }
     }
 }
 
+impl Error for MoveError {}
+
+/// Which moves `Board::get_moves_filtered` yields.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MoveFilter {
+    #[default]
+    All,
+    /// Only the tiles within `CANDIDATE_RADIUS` of a stone. Moves further away are almost never good.
+    Nearby,
+}
+
+/// How positions are written for people.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CoordStyle {
+    /// The column and then the row as base 36 digits, both starting at 0 in the top left, like `7a`. Past 35 a
+    /// number takes two digits, and the column and row are split by a comma, like `10,7`.
+    #[default]
+    Base36,
+    /// A letter for the column starting at `a` on the left, and the row as a number starting at 1 at the bottom,
+    /// like `h8`. This is what most other gomoku programs use.
+    Algebraic,
+}
+
+impl CoordStyle {
+    /// The label of a column. Algebraic columns after `z` continue with `aa`, `ab` and so on.
+    pub fn column_label(self, x: usize) -> String {
+        match self {
+            Self::Base36 => base36(x),
+            Self::Algebraic => {
+                let mut label = Vec::new();
+                let mut rest = x + 1;
+                while rest > 0 {
+                    rest -= 1;
+                    label.push(b'a' + (rest % 26) as u8);
+                    rest /= 26;
+                }
+                label.iter().rev().map(|&letter| char::from(letter)).collect()
+            }
+        }
+    }
+
+    pub fn row_label(self, y: usize, height: usize) -> String {
+        match self {
+            Self::Base36 => base36(y),
+            Self::Algebraic => (height - y).to_string(),
+        }
+    }
+
+    pub fn format(self, pos: IVec2, height: usize) -> String {
+        let column = self.column_label(pos.x as usize);
+        let row = self.row_label(pos.y as usize, height);
+        if self == Self::Base36 && (column.len() > 1 || row.len() > 1) {
+            format!("{},{}", column, row)
+        } else {
+            format!("{}{}", column, row)
+        }
+    }
+
+    /// Reads a position written by `format`. It isn't checked to be on the board, except that algebraic rows
+    /// outside of it are rejected.
+    pub fn parse(self, string: &str, height: usize) -> Option<IVec2> {
+        let string = string.trim();
+        match self {
+            Self::Base36 if string.contains(',') => {
+                let (x, y) = string.split_once(',')?;
+                let x = i32::from_str_radix(x.trim(), 36).ok().filter(|&x| x >= 0)?;
+                let y = i32::from_str_radix(y.trim(), 36).ok().filter(|&y| y >= 0)?;
+
+                Some(ivec2(x, y))
+            }
+            Self::Base36 => {
+                let mut chars = string.chars();
+                let x = chars.next()?.to_digit(36)? as i32;
+                let y = chars.next()?.to_digit(36)? as i32;
+
+                if chars.next().is_some() {
+                    return None;
+                }
+
+                Some(ivec2(x, y))
+            }
+            Self::Algebraic => {
+                let letters = string.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(string.len());
+                if letters == 0 || letters > 4 {
+                    return None;
+                }
+                let column = string[..letters]
+                    .bytes()
+                    .fold(0, |column, letter| column * 26 + (letter.to_ascii_lowercase() - b'a') as usize + 1);
+                let row = string[letters..].parse::<usize>().ok().filter(|&row| row >= 1 && row <= height)?;
+
+                Some(ivec2(column as i32 - 1, (height - row) as i32))
+            }
+        }
+    }
+}
+
+/// A number in base 36, with as many digits as it needs.
+fn base36(mut value: usize) -> String {
+    let mut digits = Vec::new();
+    loop {
+        digits.push(char::from_digit((value % 36) as u32, 36).unwrap());
+        value /= 36;
+        if value == 0 {
+            break;
+        }
+    }
+    digits.iter().rev().collect()
+}
+
 pub type Tile = Option<Player>;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Player {
+    #[default]
     A,
     B,
 }
 
-impl Default for Player {
-    fn default() -> Self {
-        Self::A
-    }
-}
-
 impl Player {
     pub fn rotate(self) -> Self {
         match self {
@@ -83,28 +262,127 @@ impl Player {
     }
 }
 
+impl Default for Board {
+    fn default() -> Self {
+        Self::new(DEFAULT_WORLD_SIZE, DEFAULT_WORLD_SIZE, DEFAULT_WIN_LENGTH)
+    }
+}
+
 impl Board {
-    pub fn new() -> Self {
-        Self::default()
+    /// Creates an empty board of the given size, where `win_length` stones in a row are needed to win.
+    pub fn new(width: usize, height: usize, win_length: i32) -> Self {
+        assert!(width > 0 && height > 0, "A board cannot be empty");
+        assert!(win_length > 0, "The win length has to be positive");
+        assert!(win_length <= MAX_WIN_LENGTH, "The win length is too long");
+
+        Self {
+            stones: Bitboard::new(width, height),
+            nearby: vec![0; width * height],
+            width,
+            height,
+            win_length,
+            rule: Rule::Freestyle,
+            opening: Opening::Free,
+            current_player: Player::A,
+            moves: 0,
+            score: 0,
+            won: None,
+            player_a_one_left: 0,
+            player_b_one_left: 0,
+            patterns: pattern_tables(win_length, Rule::Freestyle),
+            shapes: [[0; SHAPE_COUNT]; 2],
+            hash: 0,
+            history: Vec::new(),
+            redo_stack: Vec::new(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn win_length(&self) -> i32 {
+        self.win_length
+    }
+
+    /// Sets the rules of the game, this has to be done before any moves are made.
+    pub fn with_rule(mut self, rule: Rule) -> Self {
+        assert_eq!(self.moves, 0, "Cannot change the rules in the middle of a game");
+        self.rule = rule;
+        self.patterns = pattern_tables(self.win_length, rule);
+        self
+    }
+
+    pub fn rule(&self) -> Rule {
+        self.rule
+    }
+
+    /// Sets the opening the game is played with, which can restrict where the first stones are placed.
+    pub fn with_opening(mut self, opening: Opening) -> Self {
+        self.opening = opening;
+        self
+    }
+
+    pub fn opening(&self) -> Opening {
+        self.opening
     }
 
-    pub fn print(&mut self) {
-        print!("    ");
-        for i in 0..WORLD_SIZE {
-            print!("{} ", char::from_digit(i as u32, 36).expect("Cannot handle a board greater than 36 in size"));
+    /// Returns true if the opening doesn't allow the next stone at `pos`.
+    fn is_restricted_by_opening(&self, pos: IVec2) -> bool {
+        let distance = match self.opening.third_stone_distance() {
+            Some(distance) => distance,
+            None => return false,
+        };
+
+        let offset = (pos - self.center()).abs();
+        match self.moves {
+            0 => pos != self.center(),
+            2 => offset.x.max(offset.y) < distance,
+            _ => false,
         }
-        println!();
+    }
+
+    /// The position in the middle of the board.
+    pub fn center(&self) -> IVec2 {
+        ivec2(self.width as i32 / 2, self.height as i32 / 2)
+    }
+
+    pub fn print(&self) {
+        self.print_with_style(CoordStyle::default());
+    }
+
+    /// Prints the board, labeling the rows and columns in the given style.
+    pub fn print_with_style(&self, style: CoordStyle) {
+        let label_width = (0..self.height).map(|y| style.row_label(y, self.height).len()).max().unwrap_or(1);
 
-        print!("  +-");
-        for _ in 0..WORLD_SIZE {
+        // Longer column labels are written downwards, so that every column stays two characters wide.
+        let column_labels: Vec<String> = (0..self.width).map(|x| style.column_label(x)).collect();
+        let column_label_height = column_labels.iter().map(String::len).max().unwrap_or(1);
+        for line in 0..column_label_height {
+            print!("{:1$}", "", label_width + 3);
+            for label in &column_labels {
+                let padding = column_label_height - label.len();
+                let letter = if line < padding { ' ' } else { char::from(label.as_bytes()[line - padding]) };
+                print!("{} ", letter);
+            }
+            println!();
+        }
+
+        print!("{:1$}+-", "", label_width + 1);
+        for _ in 0..self.width {
             print!("--");
         }
         println!();
 
-        for (y, row) in self.grid.iter().enumerate() {
-            print!("{} | ", char::from_digit(y as u32, 36).expect("Cannot handle a board greater than 36 in size"));
+        for y in 0..self.height {
+            let row = (0..self.width).map(|x| self.stones.get(ivec2(x as i32, y as i32)));
+            print!("{:>1$} | ", style.row_label(y, self.height), label_width);
 
-            for (x, tile) in row.iter().enumerate() {
+            for (x, tile) in row.enumerate() {
                 match tile {
                     None if x % 5 == 4 && y % 5 == 4 => print!(": "), // print!("{}{}", char::from_digit(x as u32, 36).unwrap(), char::from_digit(y as u32, 36).unwrap()),
                     None if y % 5 == 4 => print!(". "),
@@ -129,124 +407,269 @@ impl Board {
         println!("B one left is {}", self.player_b_one_left);*/
     }
 
+    fn index(&self, pos: IVec2) -> Option<usize> {
+        let x = usize::try_from(pos.x).ok()?;
+        let y = usize::try_from(pos.y).ok()?;
+        if x < self.width && y < self.height {
+            Some(y * self.width + x)
+        } else {
+            None
+        }
+    }
+
+    #[inline]
     pub fn get(&self, pos: IVec2) -> Option<Tile> {
-        Some(*self.grid
-            .get(usize::try_from(pos.y).ok()?)?
-            .get(usize::try_from(pos.x).ok()?)?)
+        self.index(pos)?;
+        Some(self.stones.get(pos))
     }
 
     fn set(&mut self, pos: IVec2, tile: Tile) -> Option<()> {
-        *self.grid
-            .get_mut(usize::try_from(pos.y).ok()?)?
-            .get_mut(usize::try_from(pos.x).ok()?)?
-            = tile;
+        self.index(pos)?;
+        let old = self.stones.get(pos);
+        self.stones.set(pos, tile);
+
+        match (old, tile) {
+            (None, Some(_)) => self.update_nearby(pos, true),
+            (Some(_), None) => self.update_nearby(pos, false),
+            _ => {}
+        }
         Some(())
     }
 
+    /// Counts a stone that was placed or removed at `pos` for the tiles around it.
+    fn update_nearby(&mut self, pos: IVec2, placed: bool) {
+        for y in pos.y - CANDIDATE_RADIUS..=pos.y + CANDIDATE_RADIUS {
+            for x in pos.x - CANDIDATE_RADIUS..=pos.x + CANDIDATE_RADIUS {
+                if let Some(index) = self.index(ivec2(x, y)) {
+                    if placed {
+                        self.nearby[index] += 1;
+                    } else {
+                        self.nearby[index] -= 1;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns true if there's a stone on every position.
+    pub fn is_full(&self) -> bool {
+        self.moves >= self.width * self.height
+    }
+
+    /// Returns true if the game ended without a winner because the board is full.
+    pub fn is_draw(&self) -> bool {
+        self.won.is_none() && self.is_full()
+    }
+
+    /// Every position on the board, row by row.
+    pub fn positions(&self) -> impl Iterator<Item = IVec2> + '_ {
+        (0..self.height as i32).flat_map(move |y| (0..self.width as i32).map(move |x| ivec2(x, y)))
+    }
+
+    /// Flips whose turn it is without placing a stone. Passing again undoes it. This isn't a legal move in the
+    /// game, but searches use it to find out what a player threatens to do.
+    pub fn pass(&mut self) {
+        self.current_player = self.current_player.rotate();
+        self.hash ^= zobrist::SIDE_KEY;
+    }
+
+    /// The number of stones of `player` in an unbroken line through `pos` along `direction`, counting `pos` as
+    /// one of them, and the tiles just outside both ends of the line.
+    fn run(&self, pos: IVec2, direction: IVec2, player: Player) -> (i32, [Tile; 2]) {
+        let forward = self.stones.run(pos, direction, player);
+        let back = self.stones.run_back(pos, direction, player);
+        let ends = [
+            self.get(pos + direction * (forward + 1)).flatten(),
+            self.get(pos - direction * (back + 1)).flatten(),
+        ];
+        (1 + forward + back, ends)
+    }
+
+    /// Returns true if `player` could win the game by placing a stone at `pos`.
+    pub fn wins_at(&self, pos: IVec2, player: Player) -> bool {
+        self.get(pos) == Some(None) && self.makes_winning_line(pos, player)
+    }
+
+    /// Returns true if a stone of `player` at `pos` is part of a line that wins the game.
+    fn makes_winning_line(&self, pos: IVec2, player: Player) -> bool {
+        DIRECTIONS.iter().any(|&direction| {
+            let (length, ends) = self.run(pos, direction, player);
+            self.rule.is_winning_line(player, length, ends, self.win_length)
+        })
+    }
+
+    /// The cells of the line through `pos` along `direction`, `radius` cells in either direction, as seen by
+    /// `player`. `pos` is in the middle and counts as a stone of `player`.
+    fn line(&self, pos: IVec2, direction: IVec2, player: Player, radius: i32) -> Vec<LineCell> {
+        (-radius..=radius)
+            .map(|i| match self.get(pos + direction * i) {
+                _ if i == 0 => LineCell::Own,
+                Some(Some(owner)) if owner == player => LineCell::Own,
+                Some(None) => LineCell::Empty,
+                _ => LineCell::Blocked,
+            })
+            .collect()
+    }
+
+    /// Returns true if the rules forbid `player` from placing a stone at `pos`.
+    pub fn is_forbidden(&self, pos: IVec2, player: Player) -> bool {
+        if !self.rule.has_forbidden_moves(player) {
+            return false;
+        }
+
+        // Far enough that the straight four a three can become is seen, including the cells next to it.
+        let radius = self.win_length * 2;
+        let mut lines: Vec<_> = DIRECTIONS
+            .iter()
+            .map(|&direction| self.line(pos, direction, player, radius))
+            .collect();
+        rules::is_renju_forbidden(&mut lines, self.win_length)
+    }
+
+    /// All the positions where `player` could win the game by placing a stone.
+    pub fn winning_cells(&self, player: Player) -> Vec<IVec2> {
+        let one_left = match player {
+            Player::A => self.player_a_one_left,
+            Player::B => self.player_b_one_left,
+        };
+
+        // Every winning cell completes a line that is counted as "one left", so this skips the scan most of the time.
+        if one_left == 0 {
+            return Vec::new();
+        }
+
+        self.positions().filter(|&pos| self.wins_at(pos, player)).collect()
+    }
+
+    /// Every valid move for the current player.
     pub fn get_moves(&self) -> impl Iterator<Item = Move> + '_ {
+        self.get_moves_filtered(MoveFilter::All)
+    }
+
+    /// The valid moves for the current player that pass the filter. On an empty board every move passes.
+    pub fn get_moves_filtered(&self, filter: MoveFilter) -> impl Iterator<Item = Move> + '_ {
         let player = self.current_player;
-        (0..WORLD_SIZE as i32)
+        let nearby_only = filter == MoveFilter::Nearby && self.moves > 0;
+        (0..self.height)
             .flat_map(move |y| {
-                (0..WORLD_SIZE as i32)
-                    .filter(move |&x|
-                        matches!(self.get(ivec2(x, y)), Some(None))
-                        && (
-                            true || 
-                            matches!(self.get(ivec2(x + 1, y)),     Some(Some(_))) ||
-                            matches!(self.get(ivec2(x - 1, y)),     Some(Some(_))) ||
-                            matches!(self.get(ivec2(x, y + 1)),     Some(Some(_))) ||
-                            matches!(self.get(ivec2(x, y - 1)),     Some(Some(_))) ||
-                            matches!(self.get(ivec2(x + 1, y + 1)), Some(Some(_))) ||
-                            matches!(self.get(ivec2(x - 1, y + 1)), Some(Some(_))) ||
-                            matches!(self.get(ivec2(x + 1, y - 1)), Some(Some(_))) ||
-                            matches!(self.get(ivec2(x - 1, y - 1)), Some(Some(_)))
-                        )
-                    )
-                    .map(move |x| Move { pos: ivec2(x, y), player })
+                let mut empty = self.stones.empty_in_row(y);
+                std::iter::from_fn(move || {
+                    if empty == 0 {
+                        return None;
+                    }
+                    let x = empty.trailing_zeros();
+                    empty &= empty - 1;
+                    Some(ivec2(x as i32, y as i32))
+                })
+            })
+            .filter(move |&pos| {
+                (!nearby_only || self.nearby[pos.y as usize * self.width + pos.x as usize] > 0)
+                    && !self.is_forbidden(pos, player)
+                    && !self.is_restricted_by_opening(pos)
             })
-            .filter(move |&r#move| self.is_move_valid(r#move))
+            .map(move |pos| Move { pos, player })
     }
 
+    /// Returns true if the stone can be placed, without checking whose turn it is or if the game is over.
     pub fn is_move_valid(&self, r#move: Move) -> bool {
         !matches!(self.get(r#move.pos), Some(Some(_)) | None)
+            && !self.is_forbidden(r#move.pos, r#move.player)
+            && !self.is_restricted_by_opening(r#move.pos)
+    }
+
+    /// Checks everything about a move, including whose turn it is and if the game is over.
+    pub fn check_move(&self, r#move: Move) -> Result<(), MoveError> {
+        let Move { pos, player } = r#move;
+        if self.won.is_some() {
+            Err(MoveError::GameOver)
+        } else if player != self.current_player {
+            Err(MoveError::WrongPlayer(player))
+        } else {
+            match self.get(pos) {
+                None => Err(MoveError::OutOfBounds(pos)),
+                Some(Some(_)) => Err(MoveError::Occupied(pos)),
+                Some(None) if self.is_forbidden(pos, player) => Err(MoveError::Forbidden(pos)),
+                Some(None) if self.is_restricted_by_opening(pos) => Err(MoveError::RestrictedByOpening(pos)),
+                Some(None) => Ok(()),
+            }
+        }
     }
 
-    fn pos_directional_score(&self, pos: IVec2, direction: IVec2) -> (i32, i32, i32, Option<Player>) {
+    fn pos_directional_score(&self, pos: IVec2, direction: IVec2) -> (i32, i32, i32) {
         let mut score = 0_i32;
 
         let mut player_a_one_left = 0_i32;
         let mut player_b_one_left = 0_i32;
 
-        let mut player_a = 0_i32;
-        let mut player_b = 0_i32;
+        let win_length = self.win_length;
+        let window_mask = (1_u64 << win_length) - 1;
 
-        let mut winner = None;
+        // Every window containing `pos`, and the two windows right next to it, because the cells just outside
+        // a window can decide if it can still win. Bit `i` of the span is `i - win_length - 1` steps from `pos`,
+        // so the window starting at `start` steps is at bit `start + win_length + 1`.
+        let span_length = win_length as u32 * 2 + 3;
+        let ([span_a, span_b], on_board) = self.stones.window(pos, direction, -win_length - 1, span_length);
+        let tile_at = |bit: i32| match (span_a >> bit & 1, span_b >> bit & 1) {
+            (1, _) => Some(Player::A),
+            (_, 1) => Some(Player::B),
+            _ => None,
+        };
 
-        for i in -(WIN_LENGTH as i32)..0 {
-            match self.get(pos + direction * i) {
-                Some(Some(Player::A)) => player_a += 1,
-                Some(Some(Player::B)) => player_b += 1,
-                Some(None) => {},
-                None => {},
+        for start in -win_length..=1 {
+            let bit = start + win_length + 1;
+            if (on_board >> bit) & window_mask != window_mask {
+                continue;
             }
-        }
 
-        for i in 0 .. WIN_LENGTH as i32 {
-            match self.get(pos + direction * i) {
-                Some(Some(Player::A)) => player_a += 1,
-                Some(Some(Player::B)) => player_b += 1,
-                Some(None) => {},
-                None => {
-                    continue;
-                },
-            }
+            let player_a = ((span_a >> bit) & window_mask).count_ones() as i32;
+            let player_b = ((span_b >> bit) & window_mask).count_ones() as i32;
 
-            match self.get(pos + direction * (i - (WIN_LENGTH as i32))) {
-                Some(Some(Player::A)) => player_a -= 1,
-                Some(Some(Player::B)) => player_b -= 1,
-                Some(None) => {},
-                None => {
+            let before = tile_at(bit - 1);
+            let after = tile_at(bit + win_length);
+
+            // A full window is a win, which isn't part of the score.
+            if player_a == 0 && player_b < win_length {
+                if !self.rule.can_win_window(Player::B, before, after) {
                     continue;
-                },
-            }
+                }
 
-            if player_a == 0 {
-                if player_b == WIN_LENGTH {
-                    winner = Some(Player::B);
-                } else {
-                    if player_b == WIN_LENGTH - 1 {
-                        player_b_one_left += 1;
-                    }
+                if player_b == win_length - 1 {
+                    player_b_one_left += 1;
+                }
 
-                    score -= player_b.pow(2);
+                score -= player_b.pow(2);
+            } else if player_b == 0 && player_a < win_length {
+                if !self.rule.can_win_window(Player::A, before, after) {
+                    continue;
                 }
-            } else if player_b == 0 {
-                if player_a == WIN_LENGTH {
-                    winner = Some(Player::A);
-                } else {
-                    if player_a == WIN_LENGTH - 1 {
-                        player_a_one_left += 1;
-                    }
 
-                    score += player_a.pow(2);
+                if player_a == win_length - 1 {
+                    player_a_one_left += 1;
                 }
+
+                score += player_a.pow(2);
             }
         }
 
-        (score, player_a_one_left, player_b_one_left, winner)
+        (score, player_a_one_left, player_b_one_left)
     }
 
     pub fn score_for_position(&self, pos: IVec2) -> (i32, i32, i32, Option<Player>) {
-        let (score0, player_a_one_left0, player_b_one_left0, winner0) = self.pos_directional_score(pos, ivec2(0, 1));
-        let (score1, player_a_one_left1, player_b_one_left1, winner1) = self.pos_directional_score(pos, ivec2(1, 1));
-        let (score2, player_a_one_left2, player_b_one_left2, winner2) = self.pos_directional_score(pos, ivec2(-1, 1));
-        let (score3, player_a_one_left3, player_b_one_left3, winner3) = self.pos_directional_score(pos, ivec2(1, 0));
+        let (score0, player_a_one_left0, player_b_one_left0) = self.pos_directional_score(pos, DIRECTIONS[0]);
+        let (score1, player_a_one_left1, player_b_one_left1) = self.pos_directional_score(pos, DIRECTIONS[1]);
+        let (score2, player_a_one_left2, player_b_one_left2) = self.pos_directional_score(pos, DIRECTIONS[2]);
+        let (score3, player_a_one_left3, player_b_one_left3) = self.pos_directional_score(pos, DIRECTIONS[3]);
+
+        let winner = match self.get(pos) {
+            Some(Some(player)) if self.makes_winning_line(pos, player) => Some(player),
+            _ => None,
+        };
 
         (
             score0.saturating_add(score1).saturating_add(score2).saturating_add(score3),
             player_a_one_left0 + player_a_one_left1 + player_a_one_left2 + player_a_one_left3,
             player_b_one_left0 + player_b_one_left1 + player_b_one_left2 + player_b_one_left3,
-            winner0.or(winner1).or(winner2).or(winner3),
+            winner,
         )
     }
 
@@ -256,13 +679,66 @@ impl Board {
             score: self.score,
             player_a_one_left: self.player_a_one_left,
             player_b_one_left: self.player_b_one_left,
+            shapes: self.shapes,
+            hash: self.hash,
             board: self,
         };
-        board_handle.board.do_move(r#move);
+        board_handle.board.place(r#move);
         board_handle
     }
 
+    /// Does a move. This forgets the moves that could be redone. The move has to be valid, so moves that
+    /// come from outside should go through `try_do_move` instead.
     pub fn do_move(&mut self, r#move: Move) -> Option<Player> {
+        self.redo_stack.clear();
+        self.place(r#move)
+    }
+
+    /// Does a move if `check_move` allows it, and returns the winner like `do_move`.
+    pub fn try_do_move(&mut self, r#move: Move) -> Result<Option<Player>, MoveError> {
+        self.check_move(r#move)?;
+        Ok(self.do_move(r#move))
+    }
+
+    pub fn history(&self) -> &[Move] {
+        &self.history
+    }
+
+    /// Takes back the last move, and returns it.
+    pub fn undo(&mut self) -> Option<Move> {
+        let r#move = self.history.pop()?;
+        let pos = r#move.pos;
+
+        let (score, a_one_left, b_one_left, _) = self.score_for_position(pos);
+        self.score -= score;
+        self.player_a_one_left -= a_one_left;
+        self.player_b_one_left -= b_one_left;
+        let shapes = self.shapes_around(pos);
+        self.set(pos, None);
+        let (score, a_one_left, b_one_left, _) = self.score_for_position(pos);
+        self.score += score;
+        self.player_a_one_left += a_one_left;
+        self.player_b_one_left += b_one_left;
+        self.update_shapes(shapes, self.shapes_around(pos));
+
+        self.moves -= 1;
+        self.hash ^= zobrist::tile_key(pos, r#move.player) ^ zobrist::SIDE_KEY;
+        self.current_player = self.current_player.rotate();
+        // Nothing can be played after a win, so if anyone won it was with this move.
+        self.won = None;
+
+        self.redo_stack.push(r#move);
+        Some(r#move)
+    }
+
+    /// Does the last move that was taken back again, and returns it.
+    pub fn redo(&mut self) -> Option<Move> {
+        let r#move = self.redo_stack.pop()?;
+        self.place(r#move);
+        Some(r#move)
+    }
+
+    fn place(&mut self, r#move: Move) -> Option<Player> {
         if self.won.is_some() {
             debug_assert!(false, "Cannot do a move when someone has won");
         }
@@ -273,30 +749,418 @@ impl Board {
             debug_assert!(false, "Something is already at this spot");
         }
 
+        let winner = self.put_stone(pos, player);
+
+        self.moves += 1;
+        self.hash ^= zobrist::SIDE_KEY;
+        self.history.push(r#move);
+
+        self.current_player = self.current_player.rotate();
+
+        if let Some(winner) = winner {
+            self.won = Some(winner);
+        }
+
+        winner
+    }
+
+    /// Puts a stone on the board and updates the score, the one left counts and the hash for it, but nothing
+    /// about whose turn it is. Returns the winner if the stone makes a winning line.
+    fn put_stone(&mut self, pos: IVec2, player: Player) -> Option<Player> {
         let (score, a_one_left, b_one_left, _) = self.score_for_position(pos);
         self.score -= score;
         self.player_a_one_left -= a_one_left;
         self.player_b_one_left -= b_one_left;
+        let shapes = self.shapes_around(pos);
         let result = self.set(pos, Some(player));
         let (score, a_one_left, b_one_left, winner) = self.score_for_position(pos);
         self.score += score;
         self.player_a_one_left += a_one_left;
         self.player_b_one_left += b_one_left;
+        self.update_shapes(shapes, self.shapes_around(pos));
 
-        // If the set failed, then don't update the current player
         if result.is_none() {
             panic!("Invalid coordinate");
         }
 
-        self.moves += 1;
+        self.hash ^= zobrist::tile_key(pos, player);
+        winner
+    }
 
-        self.current_player = self.current_player.rotate();
+    /// How many stones of `player` are part of each shape, indexed by `Shape`. These are all zero if the win
+    /// length is too long for pattern tables, see `MAX_PATTERN_WIN_LENGTH`.
+    pub fn shape_counts(&self, player: Player) -> ShapeCounts {
+        self.shapes[player as usize]
+    }
 
-        if let Some(winner) = winner {
-            self.won = Some(winner);
+    /// The shapes of the stones whose patterns include `pos`, which are the stones within `win_length` of it along
+    /// each direction, counted only for that direction.
+    fn shapes_around(&self, pos: IVec2) -> [ShapeCounts; 2] {
+        let mut counts = [[0; SHAPE_COUNT]; 2];
+        let tables = match &self.patterns {
+            Some(tables) => tables,
+            None => return counts,
+        };
+
+        let reach = self.win_length;
+        for &direction in &DIRECTIONS {
+            for i in -reach..=reach {
+                let stone = pos + direction * i;
+                let player = match self.get(stone) {
+                    Some(Some(player)) => player,
+                    _ => continue,
+                };
+
+                let table = &tables[player as usize];
+                let length = table.length();
+                let (stones, on_board) = self.stones.window(stone, direction, -reach, length);
+                let own = stones[player as usize];
+                let edge = !on_board & ((1 << length) - 1);
+                if let Some(shape) = table.shape(own, stones[player.rotate() as usize], edge) {
+                    counts[player as usize][shape as usize] += 1;
+                }
+            }
         }
+        counts
+    }
 
-        winner
+    /// Replaces the shapes from `shapes_around` before a change with the ones after it.
+    fn update_shapes(&mut self, before: [ShapeCounts; 2], after: [ShapeCounts; 2]) {
+        for player in 0..2 {
+            for shape in 0..SHAPE_COUNT {
+                self.shapes[player][shape] += after[player][shape] - before[player][shape];
+            }
+        }
+    }
+
+    /// Sets up a board from the text notation written by `to_notation`.
+    ///
+    /// The notation is the rows of the board from the top, separated by `/`, where `x` is a stone of `Player::A`,
+    /// `o` is a stone of `Player::B` and a number is that many empty tiles. After the rows come the player to move,
+    /// the rule and the win length, separated by spaces, like `x1o/3/3 o freestyle 3`. The stones aren't in the
+    /// history of the board, so they can't be undone.
+    pub fn from_notation(notation: &str) -> Result<Self, NotationError> {
+        let mut fields = notation.split_whitespace();
+        let rows = fields.next().ok_or(NotationError::MissingField("rows"))?;
+        let player = match fields.next().ok_or(NotationError::MissingField("player"))? {
+            "x" => Player::A,
+            "o" => Player::B,
+            other => return Err(NotationError::InvalidPlayer(other.to_string())),
+        };
+        let rule = fields.next().ok_or(NotationError::MissingField("rule"))?;
+        let rule = rule.parse::<Rule>().map_err(|_| NotationError::InvalidRule(rule.to_string()))?;
+        let win_length = fields.next().ok_or(NotationError::MissingField("win length"))?;
+        let win_length = win_length
+            .parse::<i32>()
+            .ok()
+            .filter(|win_length| (1..=MAX_WIN_LENGTH).contains(win_length))
+            .ok_or_else(|| NotationError::InvalidWinLength(win_length.to_string()))?;
+        if let Some(extra) = fields.next() {
+            return Err(NotationError::Trailing(extra.to_string()));
+        }
+
+        let mut stones = Vec::new();
+        let mut width = None;
+        let rows: Vec<&str> = rows.split('/').collect();
+        for (y, row) in rows.iter().enumerate() {
+            let mut x: usize = 0;
+            let mut empty: usize = 0;
+            for c in row.chars() {
+                if let Some(digit) = c.to_digit(10) {
+                    empty = empty.saturating_mul(10).saturating_add(digit as usize);
+                    continue;
+                }
+
+                x = x.saturating_add(std::mem::take(&mut empty));
+                match c {
+                    'x' => stones.push((ivec2(x as i32, y as i32), Player::A)),
+                    'o' => stones.push((ivec2(x as i32, y as i32), Player::B)),
+                    _ => return Err(NotationError::InvalidRow(row.to_string())),
+                }
+                x += 1;
+            }
+            x = x.saturating_add(empty);
+
+            if x == 0 || width.is_some_and(|width| width != x) {
+                return Err(NotationError::InvalidRow(row.to_string()));
+            }
+            width = Some(x);
+        }
+
+        let width = width.unwrap_or(0);
+        if width > MAX_LINE_LENGTH || rows.len() > MAX_LINE_LENGTH {
+            return Err(NotationError::InvalidSize(width, rows.len()));
+        }
+
+        let mut board = Self::new(width, rows.len(), win_length).with_rule(rule);
+        for (pos, player) in stones {
+            if let Some(winner) = board.put_stone(pos, player) {
+                board.won = Some(winner);
+            }
+            board.moves += 1;
+        }
+        board.current_player = player;
+        if player == Player::B {
+            board.hash ^= zobrist::SIDE_KEY;
+        }
+
+        Ok(board)
+    }
+
+    /// Writes the board in the text notation read by `from_notation`.
+    pub fn to_notation(&self) -> String {
+        let mut rows = Vec::with_capacity(self.height);
+        for y in 0..self.height {
+            let mut row = String::new();
+            let mut empty = 0;
+            for x in 0..self.width {
+                let c = match self.stones.get(ivec2(x as i32, y as i32)) {
+                    None => {
+                        empty += 1;
+                        continue;
+                    }
+                    Some(Player::A) => 'x',
+                    Some(Player::B) => 'o',
+                };
+                if empty > 0 {
+                    row.push_str(&std::mem::take(&mut empty).to_string());
+                }
+                row.push(c);
+            }
+            if empty > 0 {
+                row.push_str(&empty.to_string());
+            }
+            rows.push(row);
+        }
+
+        let player = match self.current_player {
+            Player::A => 'x',
+            Player::B => 'o',
+        };
+        format!("{} {} {} {}", rows.join("/"), player, self.rule, self.win_length)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotationError {
+    /// The notation ended before this field.
+    MissingField(&'static str),
+    /// A row with something other than stones and numbers in it, or with a different width than the first row.
+    InvalidRow(String),
+    /// The width and height of a board that is too big.
+    InvalidSize(usize, usize),
+    InvalidPlayer(String),
+    InvalidRule(String),
+    InvalidWinLength(String),
+    Trailing(String),
+}
+
+impl fmt::Display for NotationError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingField(field) => write!(fmt, "the notation has no {}", field),
+            Self::InvalidRow(row) => write!(fmt, "invalid row {:?}", row),
+            Self::InvalidSize(width, height) => write!(fmt, "a {}x{} board is too big", width, height),
+            Self::InvalidPlayer(player) => write!(fmt, "invalid player {:?}, expected x or o", player),
+            Self::InvalidRule(rule) => write!(fmt, "unknown rule {:?}", rule),
+            Self::InvalidWinLength(win_length) => write!(fmt, "invalid win length {:?}", win_length),
+            Self::Trailing(text) => write!(fmt, "unexpected {:?} after the win length", text),
+        }
     }
 }
 
+impl Error for NotationError {}
+
+/// The pattern tables of both players, or `None` if the win length is too long for them.
+fn pattern_tables(win_length: i32, rule: Rule) -> Option<[Arc<PatternTable>; 2]> {
+    Some([
+        PatternTable::get(win_length, rule, Player::A)?,
+        PatternTable::get(win_length, rule, Player::B)?,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::Rng;
+
+    fn play(board: &mut Board, moves: &[(i32, i32)]) {
+        for &(x, y) in moves {
+            board.do_move(Move { pos: ivec2(x, y), player: board.current_player });
+        }
+    }
+
+    /// Everything a board keeps track of incrementally.
+    #[derive(Debug, PartialEq)]
+    struct State {
+        hash: u64,
+        score: i32,
+        one_left: [i32; 2],
+        shapes: [ShapeCounts; 2],
+        nearby: Vec<u8>,
+        won: Option<Player>,
+        current_player: Player,
+        moves: usize,
+    }
+
+    fn state(board: &Board) -> State {
+        State {
+            hash: board.hash,
+            score: board.score,
+            one_left: [board.player_a_one_left, board.player_b_one_left],
+            shapes: board.shapes,
+            nearby: board.nearby.clone(),
+            won: board.won,
+            current_player: board.current_player,
+            moves: board.moves,
+        }
+    }
+
+    /// Plays random moves until the game is over, and returns the state before every move and at the end.
+    fn random_game(board: &mut Board, rng: &Rng) -> Vec<State> {
+        let mut states = vec![state(board)];
+        while board.won.is_none() && !board.is_draw() {
+            let moves: Vec<_> = board.get_moves().collect();
+            board.do_move(moves[rng.below(moves.len() as u64) as usize]);
+            states.push(state(board));
+        }
+        states
+    }
+
+    #[test]
+    fn algebraic_labels() {
+        let style = CoordStyle::Algebraic;
+        for (x, label) in [(0, "a"), (25, "z"), (26, "aa"), (27, "ab"), (52, "ba"), (63, "bl")] {
+            assert_eq!(style.column_label(x), label);
+            assert_eq!(style.parse(&format!("{}3", label), 5), Some(ivec2(x as i32, 2)));
+        }
+        assert_eq!(style.parse("H8", 15), Some(ivec2(7, 7)));
+        assert_eq!(style.parse("8", 15), None);
+        assert_eq!(style.parse("h16", 15), None);
+    }
+
+    #[test]
+    fn base36_labels() {
+        let style = CoordStyle::Base36;
+        for (pos, label) in [(ivec2(7, 10), "7a"), (ivec2(35, 0), "z0"), (ivec2(36, 7), "10,7"), (ivec2(3, 63), "3,1r")] {
+            assert_eq!(style.format(pos, 64), label);
+            assert_eq!(style.parse(label, 64), Some(pos));
+        }
+        assert_eq!(style.parse("7", 64), None);
+        assert_eq!(style.parse("7,", 64), None);
+        assert_eq!(style.parse("-1,3", 64), None);
+    }
+
+    #[test]
+    fn notation_round_trip() {
+        for notation in ["x1o/3/3 o freestyle 3", "5/1xox1/2o2/5/5 x renju 4", "xo/ox x caro 2"] {
+            assert_eq!(Board::from_notation(notation).unwrap().to_notation(), notation);
+        }
+
+        let mut board = Board::new(9, 7, 4).with_rule(Rule::Standard);
+        play(&mut board, &[(4, 3), (5, 3), (4, 4), (0, 0), (8, 6)]);
+        let notation = board.to_notation();
+        assert_eq!(Board::from_notation(&notation).unwrap().to_notation(), notation);
+    }
+
+    #[test]
+    fn notation_recomputes_the_state() {
+        for rule in [Rule::Freestyle, Rule::Renju, Rule::Standard, Rule::Caro] {
+            let mut board = Board::new(8, 8, 5).with_rule(rule);
+            // Four in a row for `Player::A`, and then the fifth.
+            play(&mut board, &[(1, 1), (1, 2), (2, 1), (2, 2), (3, 1), (3, 2), (4, 1), (7, 7)]);
+            for won in [false, true] {
+                if won {
+                    play(&mut board, &[(5, 1)]);
+                }
+
+                let loaded = Board::from_notation(&board.to_notation()).unwrap();
+                assert_eq!(loaded.score, board.score, "{}", rule);
+                assert_eq!(loaded.player_a_one_left, board.player_a_one_left, "{}", rule);
+                assert_eq!(loaded.player_b_one_left, board.player_b_one_left, "{}", rule);
+                assert_eq!(loaded.won, board.won, "{}", rule);
+                assert_eq!(loaded.won.is_some(), won, "{}", rule);
+                assert_eq!(loaded.hash, board.hash, "{}", rule);
+                assert_eq!(loaded.moves, board.moves, "{}", rule);
+                assert_eq!(loaded.current_player, board.current_player, "{}", rule);
+            }
+        }
+    }
+
+    #[test]
+    fn undo_and_redo_restore_the_state() {
+        let rng = Rng::new(5);
+        for rule in [Rule::Freestyle, Rule::Renju, Rule::Standard, Rule::Caro] {
+            let mut board = Board::new(7, 7, 4).with_rule(rule);
+            let states = random_game(&mut board, &rng);
+
+            for expected in states.iter().rev().skip(1) {
+                board.undo().unwrap();
+                assert_eq!(&state(&board), expected, "{}", rule);
+            }
+            assert!(board.undo().is_none());
+
+            for expected in states.iter().skip(1) {
+                board.redo().unwrap();
+                assert_eq!(&state(&board), expected, "{}", rule);
+            }
+            assert!(board.redo().is_none());
+        }
+    }
+
+    #[test]
+    fn handles_restore_the_state() {
+        let rng = Rng::new(6);
+        for rule in [Rule::Freestyle, Rule::Renju, Rule::Standard, Rule::Caro] {
+            let mut board = Board::new(7, 7, 4).with_rule(rule);
+            for _ in 0..8 {
+                let moves: Vec<_> = board.get_moves().collect();
+                board.do_move(moves[rng.below(moves.len() as u64) as usize]);
+            }
+
+            let expected = state(&board);
+            let history = board.history().to_vec();
+            for r#move in board.get_moves().collect::<Vec<_>>() {
+                let handle = board.do_reversible_move(r#move);
+                assert_ne!(handle.board.hash, expected.hash, "{}", rule);
+                drop(handle);
+                assert_eq!(state(&board), expected, "{}", rule);
+                assert_eq!(board.history(), history.as_slice(), "{}", rule);
+            }
+        }
+    }
+
+    #[test]
+    fn transpositions_hash_the_same() {
+        let mut a = Board::new(9, 9, 5);
+        let mut b = Board::new(9, 9, 5);
+        play(&mut a, &[(1, 1), (3, 3), (2, 2), (4, 4)]);
+        play(&mut b, &[(2, 2), (4, 4), (1, 1), (3, 3)]);
+        assert_eq!(a.hash, b.hash);
+
+        // The same stones with the other player to move, or with the colors swapped, are different positions.
+        let mut swapped = Board::new(9, 9, 5);
+        play(&mut swapped, &[(3, 3), (1, 1), (4, 4), (2, 2)]);
+        assert_ne!(a.hash, swapped.hash);
+        b.pass();
+        assert_ne!(a.hash, b.hash);
+    }
+
+    #[test]
+    fn notation_errors() {
+        let error = |notation: &str| match Board::from_notation(notation) {
+            Ok(_) => panic!("{:?} should not be read", notation),
+            Err(error) => error,
+        };
+        assert_eq!(error(""), NotationError::MissingField("rows"));
+        assert_eq!(error("3/3/3 x freestyle"), NotationError::MissingField("win length"));
+        assert!(matches!(error("3/3/3 y freestyle 3"), NotationError::InvalidPlayer(_)));
+        assert!(matches!(error("3/3/3 x gomoku 3"), NotationError::InvalidRule(_)));
+        assert!(matches!(error("3/2/3 x freestyle 3"), NotationError::InvalidRow(_)));
+        assert!(matches!(error("3/3/3 x freestyle 0"), NotationError::InvalidWinLength(_)));
+        assert!(matches!(error("3/3/3 x freestyle 40"), NotationError::InvalidWinLength(_)));
+        assert_eq!(error("70/70 x freestyle 5"), NotationError::InvalidSize(70, 2));
+        assert_eq!(error("99999999999999999999999 x freestyle 5"), NotationError::InvalidSize(usize::MAX, 1));
+        assert!(matches!(error("3/3/3 x freestyle 3 x"), NotationError::Trailing(_)));
+    }
+}
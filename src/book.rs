@@ -0,0 +1,362 @@
+//! Opening books, and reading them from RenLib `.lib` files.
+//!
+//! A RenLib file is a 20 byte header followed by the nodes of a tree of moves in depth first order. Every node is
+//! a position byte and a flag byte. The position is `x + 1 + 16 * y` with `x` and `y` starting at zero in the top
+//! left corner of a 15x15 board, and zero means no move. The flags say if the node has children, if it has more
+//! siblings after its subtree, and if a comment follows the node.
+
+use crate::Ai;
+use crate::board::{Board, Move, Player};
+use crate::opening::Swap2Choice;
+use crate::time_control::TimeControl;
+use glam::{IVec2, ivec2};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+/// RenLib libraries are always for a 15x15 board.
+const RENLIB_SIZE: usize = 15;
+const RENLIB_WIN_LENGTH: i32 = 5;
+const RENLIB_HEADER_LENGTH: usize = 20;
+const RENLIB_MAGIC: &[u8] = b"\xffRenLib\xff";
+
+const FLAG_DOWN: u8 = 0x80;
+const FLAG_RIGHT: u8 = 0x40;
+const FLAG_OLD_COMMENT: u8 = 0x20;
+const FLAG_MARK: u8 = 0x10;
+const FLAG_COMMENT: u8 = 0x08;
+const FLAG_NO_MOVE: u8 = 0x02;
+const FLAG_EXTENSION: u8 = 0x01;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RenLibError {
+    InvalidHeader,
+    /// The file ended in the middle of a node, at this byte offset.
+    UnexpectedEnd(usize),
+}
+
+impl fmt::Display for RenLibError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidHeader => write!(fmt, "not a renlib file"),
+            Self::UnexpectedEnd(offset) => write!(fmt, "the renlib file ends in the middle of a node at byte {}", offset),
+        }
+    }
+}
+
+impl Error for RenLibError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BookMove {
+    pub pos: IVec2,
+    /// Marked moves are the ones the author of the book recommends.
+    pub marked: bool,
+    pub comment: Option<String>,
+}
+
+/// Moves to play in known positions, keyed by the zobrist hash of the position.
+#[derive(Debug, Clone)]
+pub struct OpeningBook {
+    width: usize,
+    height: usize,
+    positions: HashMap<u64, Vec<BookMove>>,
+}
+
+struct RenLibNode {
+    pos: Option<IVec2>,
+    flags: u8,
+    comment: Option<String>,
+}
+
+impl OpeningBook {
+    /// An empty book for boards of the given size.
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            positions: HashMap::new(),
+        }
+    }
+
+    /// The number of positions in the book.
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Adds a move to play on `board`. A move that is already in the book for the position is merged with it.
+    pub fn insert(&mut self, board: &Board, book_move: BookMove) {
+        let moves = self.positions.entry(board.hash).or_default();
+        match moves.iter_mut().find(|existing| existing.pos == book_move.pos) {
+            Some(existing) => {
+                existing.marked |= book_move.marked;
+                if existing.comment.is_none() {
+                    existing.comment = book_move.comment;
+                }
+            }
+            None => moves.push(book_move),
+        }
+    }
+
+    /// The moves the book knows for this position.
+    pub fn moves(&self, board: &Board) -> &[BookMove] {
+        if board.width() != self.width || board.height() != self.height {
+            return &[];
+        }
+
+        self.positions.get(&board.hash).map_or(&[], Vec::as_slice)
+    }
+
+    /// Reads every position of a RenLib library. Moves that aren't valid, and everything after them, are skipped.
+    pub fn from_renlib(bytes: &[u8]) -> Result<Self, RenLibError> {
+        if bytes.len() < RENLIB_HEADER_LENGTH || !bytes.starts_with(RENLIB_MAGIC) {
+            return Err(RenLibError::InvalidHeader);
+        }
+
+        let nodes = parse_renlib_nodes(&bytes[RENLIB_HEADER_LENGTH..])?;
+
+        let mut book = Self::new(RENLIB_SIZE, RENLIB_SIZE);
+        let mut board = Board::new(RENLIB_SIZE, RENLIB_SIZE, RENLIB_WIN_LENGTH);
+        // The nodes from the root to the current node, and whether their move was done on the board. If a move
+        // wasn't valid nothing below it is either.
+        let mut path: Vec<(bool, bool)> = Vec::new();
+        // The depths at which the next siblings should be added, once the subtree before them is done.
+        let mut siblings: Vec<usize> = Vec::new();
+
+        for node in nodes {
+            let parent_valid = path.last().is_none_or(|&(valid, _)| valid);
+            let mut applied = false;
+            let mut valid = parent_valid;
+
+            if let Some(pos) = node.pos {
+                let r#move = Move { pos, player: board.current_player };
+                if parent_valid && board.won.is_none() && board.is_move_valid(r#move) {
+                    book.insert(&board, BookMove {
+                        pos,
+                        marked: node.flags & FLAG_MARK != 0,
+                        comment: node.comment,
+                    });
+                    board.do_move(r#move);
+                    applied = true;
+                } else {
+                    valid = false;
+                }
+            }
+
+            if node.flags & FLAG_RIGHT != 0 {
+                siblings.push(path.len());
+            }
+            path.push((valid, applied));
+
+            if node.flags & FLAG_DOWN == 0 {
+                let depth = match siblings.pop() {
+                    Some(depth) => depth,
+                    None => break,
+                };
+
+                while path.len() > depth {
+                    if let Some((_, true)) = path.pop() {
+                        board.undo();
+                    }
+                }
+            }
+        }
+
+        Ok(book)
+    }
+}
+
+fn parse_renlib_nodes(bytes: &[u8]) -> Result<Vec<RenLibNode>, RenLibError> {
+    let mut nodes = Vec::new();
+    let mut offset = 0;
+    let header = RENLIB_HEADER_LENGTH;
+
+    while offset < bytes.len() {
+        let (pos, flags) = match bytes.get(offset..offset + 2) {
+            Some(&[pos, flags]) => (pos, flags),
+            _ => return Err(RenLibError::UnexpectedEnd(header + offset)),
+        };
+        offset += 2;
+
+        if flags & FLAG_EXTENSION != 0 {
+            // More flags that aren't used here.
+            offset += 2;
+        }
+
+        let comment = if flags & (FLAG_COMMENT | FLAG_OLD_COMMENT) != 0 {
+            let length = bytes
+                .get(offset..)
+                .and_then(|rest| rest.iter().position(|&byte| byte == 0))
+                .ok_or(RenLibError::UnexpectedEnd(header + offset))?;
+            let comment = String::from_utf8_lossy(&bytes[offset..offset + length]).into_owned();
+            // The text is stored in pairs of bytes, so an odd length with the terminator is padded.
+            offset += (length + 2) & !1;
+            Some(comment)
+        } else {
+            None
+        };
+
+        let x = (pos % 16) as i32 - 1;
+        let y = (pos / 16) as i32;
+        let pos = if pos == 0 || flags & FLAG_NO_MOVE != 0 || x < 0 {
+            None
+        } else {
+            Some(ivec2(x, y))
+        };
+
+        nodes.push(RenLibNode { pos, flags, comment });
+    }
+
+    Ok(nodes)
+}
+
+/// Plays moves from an opening book while the position is in it, and lets another `Ai` play after that. Marked
+/// moves are preferred.
+#[derive(Clone)]
+pub struct Book<T>(pub Arc<OpeningBook>, pub T);
+
+impl<T> Book<T> {
+    fn book_move(&self, board: &Board) -> Option<Move> {
+        let moves = self.0.moves(board);
+        moves
+            .iter()
+            .filter(|book_move| book_move.marked)
+            .chain(moves.iter())
+            .map(|book_move| Move { pos: book_move.pos, player: board.current_player })
+            .find(|&r#move| board.is_move_valid(r#move))
+    }
+}
+
+impl<T> Ai for Book<T> where T: Ai {
+    fn requires_user_output(&self) -> bool {
+        self.1.requires_user_output()
+    }
+
+    fn name(&self) -> &str { "Book" }
+
+    fn set_time_control(&mut self, time_control: TimeControl) {
+        self.1.set_time_control(time_control);
+    }
+
+    fn set_memory_limit(&mut self, bytes: u64) {
+        self.1.set_memory_limit(bytes);
+    }
+
+    fn seed(&self, seed: u64) {
+        self.1.seed(seed);
+    }
+
+    fn pick_move(&self, board: &mut Board) -> Option<Move> {
+        self.book_move(board).or_else(|| self.1.pick_move(board))
+    }
+
+    fn place_opening(&self, board: &mut Board, count: usize) -> Option<Vec<Move>> {
+        self.1.place_opening(board, count)
+    }
+
+    fn choose_color(&self, board: &mut Board) -> Option<Player> {
+        self.1.choose_color(board)
+    }
+
+    fn choose_swap2(&self, board: &mut Board) -> Option<Swap2Choice> {
+        self.1.choose_swap2(board)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Always plays the same move, to see when the book gives up.
+    struct Fixed(IVec2);
+
+    impl Ai for Fixed {
+        fn name(&self) -> &str {
+            "Fixed"
+        }
+
+        fn pick_move(&self, board: &mut Board) -> Option<Move> {
+            Some(Move { pos: self.0, player: board.current_player })
+        }
+    }
+
+    fn renlib_pos(x: u8, y: u8) -> u8 {
+        x + 1 + 16 * y
+    }
+
+    /// The center, then two replies to it. The first has a comment and one marked answer, the second has extra
+    /// flags and two answers of which the second is marked and has a comment.
+    fn library() -> Vec<u8> {
+        let mut bytes = RENLIB_MAGIC.to_vec();
+        bytes.resize(RENLIB_HEADER_LENGTH, 0);
+        bytes.extend_from_slice(&[renlib_pos(7, 7), FLAG_DOWN]);
+        bytes.extend_from_slice(&[renlib_pos(8, 7), FLAG_DOWN | FLAG_RIGHT | FLAG_COMMENT]);
+        bytes.extend_from_slice(b"main\0\0");
+        bytes.extend_from_slice(&[renlib_pos(8, 8), FLAG_MARK]);
+        bytes.extend_from_slice(&[renlib_pos(6, 6), FLAG_DOWN | FLAG_EXTENSION, 0, 0]);
+        bytes.extend_from_slice(&[renlib_pos(5, 5), FLAG_RIGHT]);
+        bytes.extend_from_slice(&[renlib_pos(9, 9), FLAG_MARK | FLAG_COMMENT]);
+        bytes.extend_from_slice(b"good!\0");
+        bytes
+    }
+
+    fn board_after(moves: &[(i32, i32)]) -> Board {
+        let mut board = Board::new(RENLIB_SIZE, RENLIB_SIZE, RENLIB_WIN_LENGTH);
+        for &(x, y) in moves {
+            board.do_move(Move { pos: ivec2(x, y), player: board.current_player });
+        }
+        board
+    }
+
+    #[test]
+    fn reads_the_tree() {
+        let book = OpeningBook::from_renlib(&library()).unwrap();
+        assert_eq!(book.len(), 4);
+
+        let positions: Vec<IVec2> = book.moves(&board_after(&[])).iter().map(|book_move| book_move.pos).collect();
+        assert_eq!(positions, [ivec2(7, 7)]);
+
+        let replies = book.moves(&board_after(&[(7, 7)]));
+        assert_eq!(replies.len(), 2);
+        assert_eq!(replies[0], BookMove { pos: ivec2(8, 7), marked: false, comment: Some("main".to_string()) });
+        assert_eq!(replies[1], BookMove { pos: ivec2(6, 6), marked: false, comment: None });
+
+        assert_eq!(
+            book.moves(&board_after(&[(7, 7), (8, 7)])),
+            [BookMove { pos: ivec2(8, 8), marked: true, comment: None }]
+        );
+        assert_eq!(
+            book.moves(&board_after(&[(7, 7), (6, 6)])),
+            [
+                BookMove { pos: ivec2(5, 5), marked: false, comment: None },
+                BookMove { pos: ivec2(9, 9), marked: true, comment: Some("good!".to_string()) },
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_broken_files() {
+        assert_eq!(OpeningBook::from_renlib(b"RenLib").unwrap_err(), RenLibError::InvalidHeader);
+
+        let mut bytes = library();
+        bytes.pop();
+        assert!(matches!(OpeningBook::from_renlib(&bytes), Err(RenLibError::UnexpectedEnd(_))));
+    }
+
+    #[test]
+    fn plays_book_moves() {
+        let book = Book(Arc::new(OpeningBook::from_renlib(&library()).unwrap()), Fixed(ivec2(0, 0)));
+
+        let pick = |moves: &[(i32, i32)]| book.pick_move(&mut board_after(moves)).map(|r#move| r#move.pos);
+        assert_eq!(pick(&[]), Some(ivec2(7, 7)));
+        assert_eq!(pick(&[(7, 7)]), Some(ivec2(8, 7)));
+        assert_eq!(pick(&[(7, 7), (6, 6)]), Some(ivec2(9, 9)));
+        assert_eq!(pick(&[(7, 7), (6, 6), (9, 9)]), Some(ivec2(0, 0)));
+        // Off the book, even if the stones would be in it on a different move order.
+        assert_eq!(pick(&[(7, 7), (1, 1)]), Some(ivec2(0, 0)));
+    }
+}
@@ -1,17 +1,38 @@
 use std::cmp::Ord;
-use std::fmt::{self, Write};
-use glam::ivec2;
+use std::fmt;
+use std::time::Instant;
 use board::*;
-use user_input::{UserInput, UserInputWithHelper};
+use user_input::UserInput;
 use minmax::MinMax;
+use opening::{Opening, Swap2Choice};
+use pattern::{Shape, ShapeCounts};
 use random::Random;
+use record::{GameRecord, RecordedMove};
 use switch::Switch;
+use time_control::TimeControl;
 
 mod switch;
+mod threat;
+mod tournament;
+mod time_control;
+mod transposition;
+mod zobrist;
+mod opening;
+mod pattern;
+mod pbrain;
+mod proof;
+mod psq;
 mod random;
+mod record;
+mod rules;
+mod sprt;
 mod user_input;
+mod mcts;
 mod minmax;
+mod bench;
+mod bitboard;
 mod board;
+mod book;
 
 /// A function that can rate how good a board is for the current player.
 pub trait ScoringFunction {
@@ -25,12 +46,78 @@ pub trait Ai {
     fn requires_user_output(&self) -> bool { false }
     fn name(&self) -> &str;
 
+    /// Tells the `Ai` how much time it may use for its moves from now on. An `Ai` that doesn't care about time
+    /// can ignore this.
+    fn set_time_control(&mut self, _time_control: TimeControl) {}
+
+    /// Tells the `Ai` roughly how many bytes of memory it may use. An `Ai` that doesn't keep big tables can
+    /// ignore this.
+    fn set_memory_limit(&mut self, _bytes: u64) {}
+
+    /// Seeds the randomness of the `Ai`, so that a match can be played again exactly the same way. An `Ai`
+    /// without randomness can ignore this.
+    fn seed(&self, _seed: u64) {}
+
     /// This function should return which move it will make on a given board.
     /// The reason the board variable is mutable is so that the Ai can play around with it as a scratch-pad
     /// of sorts, after the function returns the state of the board should not have changed.
     ///
-    /// The move returned has to be valid.
+    /// The move returned has to be valid, `run_match` counts an invalid move as a loss.
     fn pick_move(&self, board: &mut Board) -> Option<Move>;
+
+    /// Places `count` stones at the start of the game for openings like swap, alternating between the colors
+    /// starting with the current player. Like in `pick_move` the board should not have changed when this returns.
+    fn place_opening(&self, board: &mut Board, count: usize) -> Option<Vec<Move>> {
+        opening::place_with_pick_move(self, board, count)
+    }
+
+    /// Picks which color to play as, after the opponent has placed the opening stones.
+    fn choose_color(&self, board: &mut Board) -> Option<Player> {
+        Some(board.current_player)
+    }
+
+    /// Decides what to do after the opponent has placed the first three stones of a swap2 opening.
+    fn choose_swap2(&self, board: &mut Board) -> Option<Swap2Choice> {
+        self.choose_color(board).map(Swap2Choice::Color)
+    }
+}
+
+impl<T> Ai for Box<T> where T: Ai + ?Sized {
+    fn requires_user_output(&self) -> bool {
+        (**self).requires_user_output()
+    }
+
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    fn set_time_control(&mut self, time_control: TimeControl) {
+        (**self).set_time_control(time_control);
+    }
+
+    fn set_memory_limit(&mut self, bytes: u64) {
+        (**self).set_memory_limit(bytes);
+    }
+
+    fn seed(&self, seed: u64) {
+        (**self).seed(seed);
+    }
+
+    fn pick_move(&self, board: &mut Board) -> Option<Move> {
+        (**self).pick_move(board)
+    }
+
+    fn place_opening(&self, board: &mut Board, count: usize) -> Option<Vec<Move>> {
+        (**self).place_opening(board, count)
+    }
+
+    fn choose_color(&self, board: &mut Board) -> Option<Player> {
+        (**self).choose_color(board)
+    }
+
+    fn choose_swap2(&self, board: &mut Board) -> Option<Swap2Choice> {
+        (**self).choose_swap2(board)
+    }
 }
 
 #[derive(Default, Clone, Copy)]
@@ -38,6 +125,10 @@ pub struct BasicScore;
 
 impl ScoringFunction for BasicScore {
     fn score(&self, board: &mut Board) -> Score {
+        if board.is_draw() {
+            return Score::DRAW;
+        }
+
         Score(
             if board.current_player == Player::A {
                 if board.won == Some(Player::B) {
@@ -61,12 +152,17 @@ impl ScoringFunction for BasicScore {
 pub struct BetterBasicScore;
 
 impl ScoringFunction for BetterBasicScore {
+    #[allow(clippy::overly_complex_bool_expr)]
     fn score(&self, board: &mut Board) -> Score {
+        if board.is_draw() {
+            return Score::DRAW;
+        }
+
         let score = match board.current_player {
             Player::A => {
                 if board.player_a_one_left >= 1 {
                     ScoreThing::Max
-                } else if false &&  board.player_b_one_left >= 2 {
+                } else if false && board.player_b_one_left >= 2 {
                     ScoreThing::Min
                 } else if board.won == Some(Player::A) {
                     ScoreThing::Max
@@ -98,6 +194,53 @@ impl ScoringFunction for BetterBasicScore {
     }
 }
 
+/// Scores the board by the shapes the stones make, like open threes and fours, with a weight for every shape.
+#[derive(Clone, Copy)]
+pub struct PatternScore {
+    /// How much every stone in a shape is worth, indexed by `Shape`.
+    pub weights: ShapeCounts,
+}
+
+impl Default for PatternScore {
+    fn default() -> Self {
+        Self { weights: [2, 10, 20, 100, 150, 2000, 0] }
+    }
+}
+
+impl ScoringFunction for PatternScore {
+    fn score(&self, board: &mut Board) -> Score {
+        if board.is_draw() {
+            return Score::DRAW;
+        }
+
+        let player = board.current_player;
+        let own = board.shape_counts(player);
+        let opponent = board.shape_counts(player.rotate());
+
+        let score = if board.won == Some(player) {
+            ScoreThing::Max
+        } else if board.won == Some(player.rotate()) {
+            ScoreThing::Min
+        } else if own[Shape::Four as usize] + own[Shape::OpenFour as usize] > 0 {
+            // A four can be made into a five right away.
+            ScoreThing::Max
+        } else if opponent[Shape::OpenFour as usize] > 0 {
+            // Only one end of an open four can be blocked.
+            ScoreThing::Min
+        } else {
+            ScoreThing::Score(
+                self.weights
+                    .iter()
+                    .zip(own.iter().zip(opponent.iter()))
+                    .map(|(weight, (own, opponent))| weight * (own - opponent))
+                    .sum(),
+            )
+        };
+
+        Score(score, -(board.moves as i32))
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ScoreThing {
     Max,
@@ -138,6 +281,7 @@ impl Ord for ScoreThing {
 
 impl ScoreThing {
     /// Adds a score to this value
+    #[allow(clippy::should_implement_trait)]
     pub fn add(self, score: i32) -> Self {
         match self {
             Self::Max => Self::Max,
@@ -159,7 +303,15 @@ impl ScoreThing {
 pub struct Score(ScoreThing, i32);
 
 impl Score {
+    /// A score that is lower than or equal to every other score.
+    pub const MIN: Self = Self(ScoreThing::Min, -i32::MAX);
+    /// A score that is greater than or equal to every other score.
+    pub const MAX: Self = Self(ScoreThing::Max, i32::MAX);
+    /// The score of a game that ended without a winner.
+    pub const DRAW: Self = Self(ScoreThing::Score(0), 0);
+
     /// Adds a score to this value
+    #[allow(clippy::should_implement_trait)]
     pub fn add(self, score: i32) -> Self {
         Self(self.0.add(score), self.1)
     }
@@ -167,80 +319,331 @@ impl Score {
     pub fn invert(self) -> Self {
         Self(self.0.invert(), self.1)
     }
+
+    /// The score from the perspective of the other player. Unlike `invert` this also flips the tie-breaker.
+    pub fn negate(self) -> Self {
+        Self(self.0.invert(), -self.1)
+    }
 }
 
-pub fn run_match(player_a: impl Ai, player_b: impl Ai, print_debugging: bool) -> Player {
-    let mut board = Board::new();
+/// How a match is played.
+#[derive(Clone, Default)]
+pub struct MatchOptions {
+    /// The board the match starts on, which decides the size and the rules.
+    pub board: Board,
+    pub opening: Opening,
+    pub print_debugging: bool,
+    /// How the coordinates of the board are labeled when it's printed.
+    pub coord_style: CoordStyle,
+    /// If set, the `Ai`s are seeded with this at the start of the match, so that it can be reproduced.
+    pub seed: Option<u64>,
+}
 
-    let require_user_output = player_a.requires_user_output() || player_b.requires_user_output();
+/// How a match ended. The players are the `Ai`s of the match, `Player::A` being `player_a`, not the colors they
+/// played as.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GameResult {
+    Won(Player),
+    /// The board filled up without anyone winning.
+    Draw,
+    /// The player gave up, by not returning a move or a decision.
+    Forfeit(Player),
+    /// The player lost by returning a move that can't be done. The move isn't on the board of the record.
+    IllegalMove { culprit: Player, r#move: Move, error: MoveError },
+}
 
-    loop {
-        if print_debugging || require_user_output {
-            println!("{}s move.", player_a.name());
-            board.print();
+impl GameResult {
+    pub fn winner(&self) -> Option<Player> {
+        match *self {
+            Self::Won(player) => Some(player),
+            Self::Draw => None,
+            Self::Forfeit(player) => Some(player.rotate()),
+            Self::IllegalMove { culprit, .. } => Some(culprit.rotate()),
         }
+    }
+}
 
-        match player_a.pick_move(&mut board) {
-            Some(r#move) => {
-                let _ = board.do_move(r#move);
-                if print_debugging {
-                    println!("{} did {:?}", player_a.name(), r#move);
+/// Plays a match between two `Ai`s. Depending on the opening `player_a` doesn't necessarily play the first color.
+pub fn run_match(player_a: impl Ai, player_b: impl Ai, options: &MatchOptions) -> GameRecord {
+    let mut board = options.board.clone().with_opening(options.opening);
+    let print_debugging = options.print_debugging;
+
+    let require_user_output = player_a.requires_user_output() || player_b.requires_user_output();
+
+    if let Some(seed) = options.seed {
+        // The players get different seeds, so that two of the same `Ai` don't play the same moves.
+        player_a.seed(seed);
+        player_b.seed(zobrist::splitmix64(seed));
+    }
+
+    let mut record = GameRecord {
+        player_names: [player_a.name().to_string(), player_b.name().to_string()],
+        player_a_color: Player::A,
+        opening: options.opening,
+        start: board.clone(),
+        moves: Vec::new(),
+        result: GameResult::Draw,
+    };
+
+    let name_of = |player| if player == Player::A { player_a.name() } else { player_b.name() };
+
+    record.player_a_color = match options.opening.play(&mut board, &player_a, &player_b, &mut record.moves, print_debugging) {
+        Ok(color) => color,
+        Err(result) => {
+            if print_debugging || require_user_output {
+                match &result {
+                    GameResult::IllegalMove { culprit, error, .. } => {
+                        println!("{} made an illegal move during the opening, {}!", name_of(*culprit), error);
+                    }
+                    GameResult::Forfeit(player) => println!("{} forfeit during the opening!", name_of(*player)),
+                    _ => {}
                 }
             }
-            None => {
-                if print_debugging || require_user_output {
-                    println!("{} forfeit!", player_a.name());
-                }
-                return Player::B;
-            },
+            record.result = result;
+            return record;
         }
+    };
 
-        if let Some(won) = board.won {
+    loop {
+        if board.is_draw() {
             if print_debugging || require_user_output {
-                board.print();
-                println!("{} won!", player_a.name());
+                board.print_with_style(options.coord_style);
+                println!("The game is a draw!");
             }
-            return won;
+            record.result = GameResult::Draw;
+            return record;
         }
 
+        let (ai, player): (&dyn Ai, _) = if board.current_player == record.player_a_color {
+            (&player_a, Player::A)
+        } else {
+            (&player_b, Player::B)
+        };
+
         if print_debugging || require_user_output {
-            println!("{}s move.", player_b.name());
-            board.print();
+            println!("{}s move.", ai.name());
+            board.print_with_style(options.coord_style);
         }
 
-        match player_b.pick_move(&mut board) {
+        let start = Instant::now();
+        match ai.pick_move(&mut board) {
             Some(r#move) => {
-                let _ = board.do_move(r#move);
+                let time = start.elapsed();
+                if let Err(error) = board.try_do_move(r#move) {
+                    if print_debugging || require_user_output {
+                        println!("{} made an illegal move, {}!", ai.name(), error);
+                    }
+                    record.result = GameResult::IllegalMove { culprit: player, r#move, error };
+                    return record;
+                }
+
+                record.moves.push(RecordedMove { r#move, time });
                 if print_debugging {
-                    println!("{} did {:?}", player_b.name(), r#move);
+                    println!("{} did {:?}", ai.name(), r#move);
                 }
             }
             None => {
                 if print_debugging || require_user_output {
-                    println!("{} forfeit!", player_b.name());
+                    println!("{} forfeit!", ai.name());
                 }
-                return Player::A;
+                record.result = GameResult::Forfeit(player);
+                return record;
             },
         }
 
-        if let Some(won) = board.won {
+        if board.won.is_some() {
             if print_debugging || require_user_output {
-                board.print();
-                println!("{} won!", player_b.name());
+                board.print_with_style(options.coord_style);
+                println!("{} won!", ai.name());
             }
-            return won;
+            record.result = GameResult::Won(player);
+            return record;
         }
     }
 }
 
 fn main() {
-    run_match(
+    // Gomocup expects engines to be called "pbrain-something", and to speak the protocol without any arguments.
+    let is_pbrain_executable = std::env::args()
+        .next()
+        .and_then(|path| std::path::Path::new(&path).file_stem().map(|stem| stem.to_string_lossy().starts_with("pbrain")))
+        .unwrap_or(false);
+
+    if is_pbrain_executable || std::env::args().nth(1).as_deref() == Some("pbrain") {
+        let time_control = TimeControl::PerMove(std::time::Duration::from_secs(5));
+        let ai: Box<dyn Ai + Send> = match std::env::args().nth(2).as_deref() {
+            None | Some("minmax") => Box::new(
+                MinMax::new(BetterBasicScore, BetterBasicScore, 20, 10)
+                    .with_time_control(time_control)
+                    .with_threat_solver(threat::ThreatSolver::default()),
+            ),
+            Some("mcts") => Box::new(
+                mcts::Mcts::new(mcts::ScoredPlayout { score: PatternScore::default(), samples: 4 }, u32::MAX)
+                    .with_time_control(time_control),
+            ),
+            Some(other) => {
+                eprintln!("Unknown engine {:?}, expected minmax or mcts", other);
+                return;
+            }
+        };
+        let stdin = std::io::stdin();
+        if let Err(error) = pbrain::run(ai, stdin.lock(), std::io::stdout()) {
+            eprintln!("{}", error);
+        }
+        return;
+    }
+
+    if std::env::args().nth(1).as_deref() == Some("tournament") {
+        let games_per_pair = std::env::args().nth(2).and_then(|games| games.parse().ok()).unwrap_or(10);
+        let standings = tournament::Tournament::new(games_per_pair, MatchOptions { seed: Some(0), ..MatchOptions::default() })
+            .with_entrant("random", || Random::new(0))
+            .with_entrant("basic-2", || MinMax::new(BasicScore, BasicScore, 2, 10))
+            .with_entrant("better-2", || MinMax::new(BetterBasicScore, BetterBasicScore, 2, 10))
+            .with_entrant("better-4", || MinMax::new(BetterBasicScore, BetterBasicScore, 4, 10).with_node_limit(1_000_000))
+            .with_entrant("pattern-2", || MinMax::new(PatternScore::default(), PatternScore::default(), 2, 10))
+            .with_entrant("mcts", || mcts::Mcts::new(mcts::UniformPlayout, 500).with_trees(1).with_exploration(1.0))
+            .run();
+        print!("{}", standings);
+        return;
+    }
+
+    if std::env::args().nth(1).as_deref() == Some("bench") {
+        let games = std::env::args().nth(2).and_then(|games| games.parse().ok()).unwrap_or(200);
+        for rule in [rules::Rule::Freestyle, rules::Rule::Renju, rules::Rule::Standard, rules::Rule::Caro] {
+            println!("{}:", rule);
+            bench::run(&Board::new(15, 15, 5).with_rule(rule), games, 0);
+        }
+        return;
+    }
+
+    if std::env::args().nth(1).as_deref() == Some("solve") {
+        let notation = std::env::args().nth(2).unwrap_or_default();
+        let mut board = match Board::from_notation(&notation) {
+            Ok(board) => board,
+            Err(error) => {
+                eprintln!("Usage: solve \"<notation>\" [nodes], {}", error);
+                return;
+            }
+        };
+        let node_limit = std::env::args().nth(3).and_then(|nodes| nodes.parse().ok()).unwrap_or(1_000_000);
+
+        board.print();
+        // Away from the stones the search would mostly look at moves that don't matter, so on big boards it
+        // only looks close to them.
+        let mut solver = proof::ProofSolver::new(node_limit, 1 << 20);
+        if board.width() * board.height() > 64 {
+            println!("Only looking at moves close to the stones, so a draw might not be exact");
+            solver = solver.with_move_filter(MoveFilter::Nearby);
+        }
+        let start = Instant::now();
+        let solution = solver.solve(&mut board);
+        println!("{:?} in {:.2?}", solution, start.elapsed());
+        return;
+    }
+
+    if std::env::args().nth(1).as_deref() == Some("analyze") {
+        let path = match std::env::args().nth(2) {
+            Some(path) => path,
+            None => {
+                eprintln!("Usage: analyze <file.psq> [rule]");
+                return;
+            }
+        };
+        let rule = match std::env::args().nth(3).map(|rule| rule.parse()).transpose() {
+            Ok(rule) => rule.unwrap_or_default(),
+            Err(error) => {
+                eprintln!("{}", error);
+                return;
+            }
+        };
+
+        let board = std::fs::read_to_string(&path)
+            .map_err(|error| error.to_string())
+            .and_then(|text| psq::PsqGame::parse(&text).map_err(|error| error.to_string()))
+            .and_then(|game| game.to_board(DEFAULT_WIN_LENGTH, rule).map_err(|error| error.to_string()));
+        let mut board = match board {
+            Ok(board) => board,
+            Err(error) => {
+                eprintln!("{}: {}", path, error);
+                return;
+            }
+        };
+
+        board.print();
+        // There's time to look at every move and for deeper threats than in a game.
+        let ai = MinMax::new(BetterBasicScore, BetterBasicScore, 20, 10)
+            .with_time_control(TimeControl::PerMove(std::time::Duration::from_secs(5)))
+            .with_threat_solver(threat::ThreatSolver::new(24, 5, 500_000))
+            .with_move_filter(MoveFilter::All);
+        match ai.pick_move(&mut board) {
+            Some(r#move) => println!("Best move for {:?}: {}", r#move.player, CoordStyle::Base36.format(r#move.pos, board.height())),
+            None => println!("There are no moves left"),
+        }
+        return;
+    }
+
+    if std::env::args().nth(1).as_deref() == Some("sprt") {
+        let baseline = tournament::Entrant::new("basic-2", || MinMax::new(BasicScore, BasicScore, 2, 10));
+        let candidate = tournament::Entrant::new("better-2", || MinMax::new(BetterBasicScore, BetterBasicScore, 2, 10));
+        let outcome = sprt::Sprt::new(0.0, 50.0)
+            .with_error_rates(0.05, 0.05)
+            .with_max_games(1000)
+            .with_opening_stones(2)
+            .with_options(MatchOptions { seed: Some(0), ..MatchOptions::default() })
+            .run(&baseline, &candidate);
+        println!(
+            "{} against {}: {:?} after {} games, {} wins, {} draws and {} losses, llr {:.2}",
+            candidate.name,
+            baseline.name,
+            outcome.result,
+            outcome.tally.games(),
+            outcome.tally.wins,
+            outcome.tally.draws,
+            outcome.tally.losses,
+            outcome.llr,
+        );
+        return;
+    }
+
+    let engine = Switch(Random::default(), MinMax::new(BetterBasicScore, BetterBasicScore, 6, 10), 0);
+    // With "book <file.lib>" the engine plays from a RenLib library while the game is in it.
+    let opponent: Box<dyn Ai> = match (std::env::args().nth(1).as_deref(), std::env::args().nth(2)) {
+        (Some("book"), Some(path)) => {
+            let book = std::fs::read(&path)
+                .map_err(|error| error.to_string())
+                .and_then(|bytes| book::OpeningBook::from_renlib(&bytes).map_err(|error| error.to_string()));
+            match book {
+                Ok(book) if book.is_empty() => {
+                    eprintln!("{} has no positions that fit on the board", path);
+                    return;
+                }
+                Ok(book) => {
+                    println!("Loaded {} positions from {}", book.len(), path);
+                    Box::new(book::Book(std::sync::Arc::new(book), engine))
+                }
+                Err(error) => {
+                    eprintln!("{}: {}", path, error);
+                    return;
+                }
+            }
+        }
+        _ => Box::new(engine),
+    };
+
+    let record = run_match(
         // Switch(Random, MinMax::new(BetterBasicScore, BetterBasicScore, 5, 10), 0),
-        UserInput("Trolled".to_string()),
-        Switch(Random, MinMax::new(BetterBasicScore, BetterBasicScore, 6, 10), 0),
-        true,
+        UserInput("Trolled".to_string(), CoordStyle::Base36),
+        opponent,
+        &MatchOptions {
+            print_debugging: true,
+            ..MatchOptions::default()
+        },
     );
 
+    // So that the game can be looked at again in piskvork, or with "analyze".
+    println!("The game in the psq format:");
+    print!("{}", psq::PsqGame::from_record(&record));
+
         // Random,
         // Random,
         // UserInput("Trolled".to_string()),
@@ -0,0 +1,274 @@
+//! Monte Carlo tree search. Instead of scoring boards it plays many quick games from them, and spends more of
+//! its time on the moves that won the most of those.
+
+use crate::{Ai, ScoringFunction};
+use crate::board::{Board, Move, MoveFilter};
+use crate::random::Rng;
+use crate::time_control::TimeControl;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// Picks the moves of the quick games that are played from the leaves of the tree.
+pub trait PlayoutPolicy {
+    /// Picks one of `moves`, which aren't empty and are all valid on the board. Like in `Ai::pick_move` the
+    /// board should not have changed when this returns.
+    fn pick(&self, board: &mut Board, moves: &[Move], rng: &Rng) -> Move;
+}
+
+/// Plays uniformly random moves.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct UniformPlayout;
+
+impl PlayoutPolicy for UniformPlayout {
+    fn pick(&self, _board: &mut Board, moves: &[Move], rng: &Rng) -> Move {
+        moves[rng.below(moves.len() as u64) as usize]
+    }
+}
+
+/// Picks a few random moves, and plays the one the scoring function likes best. More samples make the games
+/// stronger but slower.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ScoredPlayout<T> {
+    pub score: T,
+    pub samples: usize,
+}
+
+impl<T> PlayoutPolicy for ScoredPlayout<T> where T: ScoringFunction {
+    fn pick(&self, board: &mut Board, moves: &[Move], rng: &Rng) -> Move {
+        (0..self.samples.max(1))
+            .map(|_| moves[rng.below(moves.len() as u64) as usize])
+            .max_by_key(|&r#move| {
+                let handle = board.do_reversible_move(r#move);
+                // The score is for the opponent, who is to move after this.
+                self.score.score(handle.board).negate()
+            })
+            .expect("There is at least one sample")
+    }
+}
+
+struct Node {
+    r#move: Option<Move>,
+    children: Vec<usize>,
+    /// The moves that don't have a child yet.
+    untried: Vec<Move>,
+    visits: u32,
+    /// The total result of the games through this node, for the player that made the move to get here. A win
+    /// counts as 1 and a draw as a half.
+    wins: f64,
+}
+
+impl Node {
+    fn new(r#move: Option<Move>, board: &Board) -> Self {
+        Self {
+            r#move,
+            children: Vec::new(),
+            untried: if board.won.is_some() { Vec::new() } else { candidate_moves(board) },
+            visits: 0,
+            wins: 0.0,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Mcts<P> {
+    pub policy: P,
+    /// How many games are played from every tree. With a time control this is the most that are played.
+    pub iterations: u32,
+    /// How many trees are searched at the same time. Their results are added together at the end.
+    pub trees: usize,
+    /// The exploration constant of UCT, higher values try more moves that haven't done well so far.
+    pub exploration: f64,
+    /// Games that go on for longer than this many moves are counted as draws.
+    pub playout_length: usize,
+    pub time_control: Option<TimeControl>,
+    pub rng: Rng,
+}
+
+impl<P> Mcts<P> {
+    pub fn new(policy: P, iterations: u32) -> Self {
+        Self {
+            policy,
+            iterations,
+            trees: rayon::current_num_threads(),
+            exploration: std::f64::consts::SQRT_2,
+            playout_length: 80,
+            time_control: None,
+            rng: Rng::from_time(),
+        }
+    }
+
+    pub fn with_trees(mut self, trees: usize) -> Self {
+        self.trees = trees.max(1);
+        self
+    }
+
+    pub fn with_exploration(mut self, exploration: f64) -> Self {
+        self.exploration = exploration;
+        self
+    }
+
+    pub fn with_time_control(mut self, time_control: TimeControl) -> Self {
+        self.time_control = Some(time_control);
+        self
+    }
+}
+
+impl<P> Ai for Mcts<P> where P: PlayoutPolicy + Send + Sync {
+    fn name(&self) -> &str {
+        "Mcts"
+    }
+
+    fn set_time_control(&mut self, time_control: TimeControl) {
+        self.time_control = Some(time_control);
+    }
+
+    fn seed(&self, seed: u64) {
+        self.rng.seed(seed);
+    }
+
+    fn pick_move(&self, board: &mut Board) -> Option<Move> {
+        let deadline = self.time_control.map(|time_control| Instant::now() + time_control.move_budget());
+        let seeds: Vec<u64> = (0..self.trees).map(|_| self.rng.next_u64()).collect();
+
+        // Every tree is searched on its own, and only the statistics of the root moves are merged.
+        let roots: Vec<Vec<(Move, u32, f64)>> = seeds
+            .into_par_iter()
+            .map_with(board.clone(), |board, seed| self.search_tree(board, deadline, &Rng::new(seed)))
+            .collect();
+
+        let mut totals: HashMap<Move, (u32, f64)> = HashMap::new();
+        for (r#move, visits, wins) in roots.into_iter().flatten() {
+            let total = totals.entry(r#move).or_default();
+            total.0 += visits;
+            total.1 += wins;
+        }
+
+        let (r#move, (visits, wins)) = totals.into_iter().max_by_key(|&(_, (visits, _))| visits)?;
+        eprintln!("Win rate from mcts: {:.3} over {} games", wins / visits.max(1) as f64, visits);
+        Some(r#move)
+    }
+}
+
+impl<P> Mcts<P> where P: PlayoutPolicy {
+    /// Builds a tree from the board, and returns the moves of the root with their visits and wins.
+    fn search_tree(&self, board: &mut Board, deadline: Option<Instant>, rng: &Rng) -> Vec<(Move, u32, f64)> {
+        let tree = self.grow_tree(board, deadline, rng);
+        tree[0]
+            .children
+            .iter()
+            .map(|&child| (tree[child].r#move.expect("Only the root has no move"), tree[child].visits, tree[child].wins))
+            .collect()
+    }
+
+    /// Builds a tree from the board, the root is the first node.
+    fn grow_tree(&self, board: &mut Board, deadline: Option<Instant>, rng: &Rng) -> Vec<Node> {
+        let mut tree = vec![Node::new(None, board)];
+        for _ in 0..self.iterations {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                break;
+            }
+
+            self.visit(&mut tree, 0, board, rng);
+        }
+        tree
+    }
+
+    /// Walks down the tree from `index`, adds a node and plays a game from it. Returns the result for the player
+    /// to move on the board. Every call counts a visit of `index`, and of the node it adds, but the wins are
+    /// added by the caller since they're for the player that moved to get to the node.
+    fn visit(&self, tree: &mut Vec<Node>, index: usize, board: &mut Board, rng: &Rng) -> f64 {
+        tree[index].visits += 1;
+        if board.won.is_some() {
+            // Only the player that just moved can have won.
+            return 0.0;
+        }
+
+        let (child, result) = if !tree[index].untried.is_empty() {
+            let untried = &mut tree[index].untried;
+            let r#move = untried.swap_remove(rng.below(untried.len() as u64) as usize);
+            let handle = board.do_reversible_move(r#move);
+            let child = tree.len();
+            tree.push(Node::new(Some(r#move), handle.board));
+            tree[index].children.push(child);
+            tree[child].visits += 1;
+            (child, 1.0 - self.playout(handle.board, rng, self.playout_length))
+        } else if let Some(child) = self.select(tree, index) {
+            let r#move = tree[child].r#move.expect("Only the root has no move");
+            let handle = board.do_reversible_move(r#move);
+            (child, 1.0 - self.visit(tree, child, handle.board, rng))
+        } else {
+            // No moves left, so the board is full.
+            return 0.5;
+        };
+
+        tree[child].wins += result;
+        result
+    }
+
+    /// The child with the best upper confidence bound.
+    fn select(&self, tree: &[Node], index: usize) -> Option<usize> {
+        let log_visits = (tree[index].visits.max(1) as f64).ln();
+        tree[index].children.iter().copied().max_by(|&a, &b| {
+            let bound = |child: usize| {
+                let node = &tree[child];
+                let visits = node.visits.max(1) as f64;
+                node.wins / visits + self.exploration * (log_visits / visits).sqrt()
+            };
+            bound(a).total_cmp(&bound(b))
+        })
+    }
+
+    /// Plays a quick game with the playout policy. Returns the result for the player to move on the board.
+    fn playout(&self, board: &mut Board, rng: &Rng, length: usize) -> f64 {
+        if board.won.is_some() {
+            return 0.0;
+        }
+
+        let moves = candidate_moves(board);
+        if length == 0 || moves.is_empty() {
+            return 0.5;
+        }
+
+        let r#move = self.policy.pick(board, &moves, rng);
+        let handle = board.do_reversible_move(r#move);
+        1.0 - self.playout(handle.board, rng, length - 1)
+    }
+}
+
+/// The valid moves close to the stones on the board. On an empty board that's just the center.
+fn candidate_moves(board: &Board) -> Vec<Move> {
+    let center = Move { pos: board.center(), player: board.current_player };
+    if board.moves == 0 && board.is_move_valid(center) {
+        return vec![center];
+    }
+
+    let moves: Vec<Move> = board.get_moves_filtered(MoveFilter::Nearby).collect();
+    if moves.is_empty() {
+        return board.get_moves().collect();
+    }
+    moves
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn visits_add_up() {
+        let mcts = Mcts::new(UniformPlayout, 500).with_trees(1);
+        for board in [Board::new(5, 5, 4), Board::new(3, 3, 3)] {
+            let tree = mcts.grow_tree(&mut board.clone(), None, &Rng::new(1));
+            assert_eq!(tree[0].visits, 500);
+
+            for (index, node) in tree.iter().enumerate() {
+                assert!(node.wins <= node.visits as f64);
+                if !node.children.is_empty() {
+                    let children: u32 = node.children.iter().map(|&child| tree[child].visits).sum();
+                    let own = if index == 0 { 0 } else { 1 };
+                    assert_eq!(node.visits, own + children, "node {}", index);
+                }
+            }
+        }
+    }
+}
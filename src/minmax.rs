@@ -1,14 +1,74 @@
-use crate::{Ai, ScoringFunction, Score, WORLD_SIZE, ScoreThing};
-use crate::board::{Board, Move};
-use glam::ivec2;
+use crate::{Ai, ScoringFunction, Score, ScoreThing};
+use crate::board::{Board, Move, MoveFilter, Player};
+use crate::opening::Swap2Choice;
+use crate::threat::ThreatSolver;
+use crate::time_control::TimeControl;
+use crate::transposition::{Bound, Entry, TranspositionTable};
 use rayon::prelude::*;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Instant;
 
-#[derive(Default, Clone, Copy)]
+#[derive(Default, Clone)]
 pub struct MinMax<T, Q> {
     pub score: T,
     pub filter_score: Q,
+    /// The depth of the search. With a time control or node limit this is the deepest iteration that is tried.
     pub depth: u32,
     pub culling: usize,
+    /// Shared between all the search threads, and kept between moves.
+    pub table: Arc<TranspositionTable>,
+    /// If this or `node_limit` is set, the search deepens one ply at a time until the budget runs out.
+    pub time_control: Option<TimeControl>,
+    pub node_limit: Option<u64>,
+    /// Looks for forced wins by threats at the root, and for wins by continuous fours at the leaves.
+    pub threat_solver: Option<ThreatSolver>,
+    /// Which moves are searched. `MinMax::new` only searches moves close to the stones.
+    pub move_filter: MoveFilter,
+}
+
+/// The budget of a single search, shared between all the search threads.
+struct SearchLimits {
+    deadline: Option<Instant>,
+    node_limit: Option<u64>,
+    nodes: AtomicU64,
+    stopped: AtomicBool,
+}
+
+impl SearchLimits {
+    /// How many nodes are searched between looking at the clock. Every node scores all its moves, so this is low.
+    const CLOCK_INTERVAL: u64 = 16;
+
+    fn unlimited() -> Self {
+        Self {
+            deadline: None,
+            node_limit: None,
+            nodes: AtomicU64::new(0),
+            stopped: AtomicBool::new(false),
+        }
+    }
+
+    /// Counts a searched node, and returns true if the search has run out of budget.
+    fn visit_node(&self) -> bool {
+        if self.stopped.load(Ordering::Relaxed) {
+            return true;
+        }
+
+        let nodes = self.nodes.fetch_add(1, Ordering::Relaxed) + 1;
+        let out_of_nodes = self.node_limit.is_some_and(|limit| nodes > limit);
+        let out_of_time = nodes.is_multiple_of(Self::CLOCK_INTERVAL)
+            && self.deadline.is_some_and(|deadline| Instant::now() >= deadline);
+
+        if out_of_nodes || out_of_time {
+            self.stopped.store(true, Ordering::Relaxed);
+        }
+
+        self.stopped.load(Ordering::Relaxed)
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
 }
 
 impl<T, Q> MinMax<T, Q> {
@@ -18,13 +78,38 @@ impl<T, Q> MinMax<T, Q> {
             filter_score,
             depth,
             culling,
+            table: Arc::default(),
+            time_control: None,
+            node_limit: None,
+            threat_solver: None,
+            move_filter: MoveFilter::Nearby,
         }
     }
+
+    pub fn with_time_control(mut self, time_control: TimeControl) -> Self {
+        self.time_control = Some(time_control);
+        self
+    }
+
+    pub fn with_node_limit(mut self, node_limit: u64) -> Self {
+        self.node_limit = Some(node_limit);
+        self
+    }
+
+    pub fn with_threat_solver(mut self, threat_solver: ThreatSolver) -> Self {
+        self.threat_solver = Some(threat_solver);
+        self
+    }
+
+    pub fn with_move_filter(mut self, move_filter: MoveFilter) -> Self {
+        self.move_filter = move_filter;
+        self
+    }
 }
 
 impl<T, Q> ScoringFunction for MinMax<T, Q> where T: ScoringFunction + Send + Sync, Q: ScoringFunction + Send + Sync {
     fn score(&self, board: &mut Board) -> Score {
-        self.do_minmax(board, self.depth).1
+        self.search(board).1
     }
 }
 
@@ -33,26 +118,106 @@ impl<T, Q> Ai for MinMax<T, Q> where T: ScoringFunction + Send + Sync, Q: Scorin
         "Min max"
     }
 
+    fn set_time_control(&mut self, time_control: TimeControl) {
+        self.time_control = Some(time_control);
+    }
+
+    fn set_memory_limit(&mut self, bytes: u64) {
+        self.table = Arc::new(TranspositionTable::with_memory_limit(bytes));
+    }
+
     fn pick_move(&self, board: &mut Board) -> Option<Move> {
-        let (r#move, score) = self.do_minmax(board, self.depth);
-        println!("Board score from minmax: {}", score.0);
+        let (r#move, score) = self.search(board);
+        // This goes to stderr, so that it doesn't get mixed up with protocols on stdout.
+        eprintln!("Board score from minmax: {}", score.0);
         r#move
     }
+
+    /// Places the stones one at a time, each time picking the stone that leaves the most even board, so that the
+    /// opponent has a hard time picking a color.
+    fn place_opening(&self, board: &mut Board, count: usize) -> Option<Vec<Move>> {
+        let mut scratch = board.clone();
+        let mut moves = Vec::with_capacity(count);
+        for _ in 0..count {
+            let center = scratch.center();
+            let r#move = scratch
+                .get_moves()
+                .filter(|r#move| {
+                    let offset = (r#move.pos - center).abs();
+                    offset.x.max(offset.y) <= Self::OPENING_RADIUS
+                })
+                .collect::<Vec<_>>()
+                .into_iter()
+                .min_by_key(|&r#move| {
+                    let handle = scratch.do_reversible_move(r#move);
+                    let imbalance = match self.score.score(handle.board).0 {
+                        ScoreThing::Score(score) => score.abs(),
+                        ScoreThing::Max | ScoreThing::Min => i32::MAX,
+                    };
+                    drop(handle);
+                    imbalance
+                })?;
+            scratch.do_move(r#move);
+            moves.push(r#move);
+        }
+        Some(moves)
+    }
+
+    fn choose_color(&self, board: &mut Board) -> Option<Player> {
+        let (_, score) = self.search(board);
+        if score >= Score(ScoreThing::Score(0), 0) {
+            Some(board.current_player)
+        } else {
+            Some(board.current_player.rotate())
+        }
+    }
+
+    fn choose_swap2(&self, board: &mut Board) -> Option<Swap2Choice> {
+        let (_, score) = self.search(board);
+        match score.0 {
+            ScoreThing::Score(score) if score.abs() <= Self::BALANCED_SCORE => Some(Swap2Choice::PlaceTwo),
+            _ if score >= Score(ScoreThing::Score(0), 0) => Some(Swap2Choice::Color(board.current_player)),
+            _ => Some(Swap2Choice::Color(board.current_player.rotate())),
+        }
+    }
 }
 
 impl<T, Q> MinMax<T, Q> where T: ScoringFunction + Send + Sync, Q: ScoringFunction + Send + Sync {
-    fn do_minmax(&self, board: &mut Board, recursion: u32) -> (Option<Move>, Score) {
-        if recursion == 0 {
-            // println!("Found board end");
-            return (None, self.score.score(board));
+    /// How far from the center opening stones are placed.
+    const OPENING_RADIUS: i32 = 3;
+    /// In swap2, a board with a score this close to zero is too even to pick a color, so two more stones are placed
+    /// instead.
+    const BALANCED_SCORE: i32 = 8;
+
+    /// Searches for the best move, with iterative deepening if there's a time or node budget. A forced win by
+    /// threats is looked for once up front, it doesn't depend on the search depth.
+    fn search(&self, board: &mut Board) -> (Option<Move>, Score) {
+        if let Some(solver) = &self.threat_solver {
+            if let Some(line) = solver.solve_vct(board) {
+                return (line.first().copied(), Score(ScoreThing::Max, self.depth as i32));
+            }
         }
 
+        if self.time_control.is_some() || self.node_limit.is_some() {
+            self.iterative_deepening(board)
+        } else {
+            self.do_minmax(board, self.depth, &SearchLimits::unlimited())
+        }
+    }
+
+    /// Gives every valid move a quick score with the filter scoring function, and returns them sorted with the
+    /// most promising moves first. Searching the best moves first is what makes the alpha-beta cutoffs effective.
+    fn ordered_moves(&self, board: &Board) -> Vec<(Move, ScoreThing)> {
         let want_to_win = board.current_player;
 
-        let temp_moves: Vec<Move> = board.get_moves().collect();
+        let mut temp_moves: Vec<Move> = board.get_moves_filtered(self.move_filter).collect();
+        if temp_moves.is_empty() {
+            // Every move close to the stones might be forbidden, while there are still moves further away.
+            temp_moves = board.get_moves().collect();
+        }
         let mut moves: Vec<(Move, ScoreThing)> = temp_moves
             .into_par_iter()
-            .map_with(*board, |board, r#move| {
+            .map_with(board.clone(), |board, r#move| {
                 let handle = board.do_reversible_move(r#move);
                 let result = match handle.board.won {
                     Some(winner) if winner == want_to_win => ScoreThing::Max,
@@ -65,46 +230,217 @@ impl<T, Q> MinMax<T, Q> where T: ScoringFunction + Send + Sync, Q: ScoringFuncti
             })
             .collect();
 
+        moves.sort_unstable_by(|(_, a), (_, b)| b.cmp(a));
+        moves
+    }
+
+    /// Moves the best move found by an earlier search of this position to the front.
+    fn promote_move(moves: &mut Vec<(Move, ScoreThing)>, r#move: Option<Move>) {
+        if let Some(index) = moves.iter().position(|&(m, _)| Some(m) == r#move) {
+            let promoted = moves.remove(index);
+            moves.insert(0, promoted);
+        }
+    }
+
+    /// Does a move and scores the resulting board from the perspective of the player doing the move.
+    fn search_move(&self, board: &mut Board, limits: &SearchLimits, r#move: Move, recursion: u32, alpha: Score, beta: Score) -> Score {
+        let want_to_win = board.current_player;
+        let handle = board.do_reversible_move(r#move);
+        let result = match handle.board.won {
+            Some(winner) if winner == want_to_win => Score(ScoreThing::Max, recursion as i32),
+            Some(_) => Score(ScoreThing::Min, recursion as i32),
+            // We take the negative here because it's the opponents move, which also means the window is flipped.
+            None => self.alpha_beta(handle.board, limits, recursion - 1, beta.negate(), alpha.negate()).negate(),
+        };
+        drop(handle);
+        result
+    }
+
+    /// A negamax search with alpha-beta pruning. The returned score is from the perspective of the current player,
+    /// and is only exact if it lies strictly between `alpha` and `beta`, otherwise it's a bound.
+    fn alpha_beta(&self, board: &mut Board, limits: &SearchLimits, recursion: u32, mut alpha: Score, beta: Score) -> Score {
+        if recursion == 0 {
+            if let Some(solver) = &self.threat_solver {
+                if solver.solve_vcf(board).is_some() {
+                    return Score(ScoreThing::Max, 0);
+                }
+            }
+
+            return self.score.score(board);
+        }
+
+        if limits.visit_node() {
+            // The result is thrown away anyway.
+            return Score(ScoreThing::Score(0), 0);
+        }
+
+        let original_alpha = alpha;
+        let mut table_move = None;
+        if let Some(entry) = self.table.get(board.hash) {
+            if entry.depth >= recursion {
+                match entry.bound {
+                    Bound::Exact => return entry.score,
+                    Bound::Lower if entry.score >= beta => return entry.score,
+                    Bound::Upper if entry.score <= alpha => return entry.score,
+                    Bound::Lower if entry.score > alpha => alpha = entry.score,
+                    _ => {}
+                }
+            }
+
+            table_move = entry.best_move;
+        }
+
+        let mut moves = self.ordered_moves(board);
+
         if moves.is_empty() {
-            return (
-                Some(Move {
-                    pos: ivec2(WORLD_SIZE as i32 / 2, WORLD_SIZE as i32 / 2),
-                    player: board.current_player,
-                }),
-                Score(ScoreThing::Score(0), 0),
-            );
+            return Score::DRAW;
         }
 
-        // Do the temporary thing
-        moves.sort_unstable_by(|(_, a), (_, b)| b.cmp(a));
+        if let Some((_, ScoreThing::Max)) = moves.first() {
+            // The best move is a guaranteed win, we can "shortcircuit"
+            return Score(ScoreThing::Max, 0);
+        }
+
+        Self::promote_move(&mut moves, table_move);
+
+        let mut best = Score::MIN;
+        let mut best_move = None;
+        for &(r#move, _) in moves.iter().take(self.culling) {
+            let result = self.search_move(board, limits, r#move, recursion, alpha, beta);
+
+            if result > best {
+                best = result;
+                best_move = Some(r#move);
+            }
+
+            if best > alpha {
+                alpha = best;
+            }
+
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        if limits.is_stopped() {
+            return best;
+        }
+
+        let bound = if best <= original_alpha {
+            Bound::Upper
+        } else if best >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+
+        self.table.store(Entry {
+            hash: board.hash,
+            depth: recursion,
+            bound,
+            best_move,
+            score: best,
+        });
+
+        best
+    }
+
+    /// Searches depth 1, 2, 3 and so on until the time or node budget runs out, and returns the result of the
+    /// deepest search that finished.
+    fn iterative_deepening(&self, board: &mut Board) -> (Option<Move>, Score) {
+        let start = Instant::now();
+        let budget = self.time_control.map(TimeControl::move_budget);
+        let limits = SearchLimits {
+            deadline: budget.map(|budget| start + budget),
+            node_limit: self.node_limit,
+            ..SearchLimits::unlimited()
+        };
+
+        // The first iteration always has to finish, otherwise we have no move at all.
+        let mut best = self.do_minmax(board, 1, &SearchLimits::unlimited());
 
-        if let Some(&(r#move, ScoreThing::Max)) = moves.get(0) {
+        for depth in 2..=self.depth {
+            if matches!(best.1, Score(ScoreThing::Max | ScoreThing::Min, _)) {
+                // The game is decided, searching deeper won't change anything.
+                break;
+            }
+
+            if let Some(budget) = budget {
+                // The next iteration takes several times longer than the previous one, so if more than half of the
+                // budget is used there is no point in starting it.
+                if start.elapsed() * 2 > budget {
+                    break;
+                }
+            }
+
+            let result = self.do_minmax(board, depth, &limits);
+            if limits.is_stopped() {
+                break;
+            }
+
+            best = result;
+        }
+
+        best
+    }
+
+    fn do_minmax(&self, board: &mut Board, recursion: u32, limits: &SearchLimits) -> (Option<Move>, Score) {
+        if recursion == 0 {
+            // println!("Found board end");
+            return (None, self.score.score(board));
+        }
+
+        let mut moves = self.ordered_moves(board);
+
+        if moves.is_empty() {
+            // There's nowhere left to play, so the game is a draw.
+            return (None, Score::DRAW);
+        }
+
+        if let Some(&(r#move, ScoreThing::Max)) = moves.first() {
             // The best move is a guaranteed win, we can "shortcircuit"
             return (Some(r#move), Score(ScoreThing::Max, 0));
         }
 
-        if let Some((_, ScoreThing::Min)) = moves.get(0) {
+        if let Some((_, ScoreThing::Min)) = moves.first() {
             // @Robustness: We should make a check here, I don't think this case should ever trigger
         }
 
-        // Do the full scoring of the top moves
-        moves[..self.culling]
+        Self::promote_move(&mut moves, self.table.get(board.hash).and_then(|entry| entry.best_move));
+
+        // The most promising move is searched first with a full window, the rest are searched in parallel and only
+        // have to prove that they're better than it.
+        let (&(first_move, _), rest) = moves[..self.culling.clamp(1, moves.len())]
+            .split_first()
+            .unwrap();
+        let first_score = self.search_move(board, limits, first_move, recursion, Score::MIN, Score::MAX);
+
+        let best_rest = rest
             .par_iter()
-            .map_with(*board, |board, &(r#move, _)| {
-                let handle = board.do_reversible_move(r#move);
-                let result = match handle.board.won {
-                    Some(winner) if winner == want_to_win => Score(ScoreThing::Max, recursion as i32),
-                    Some(_) => Score(ScoreThing::Min, recursion as i32),
-                    // We take the negative here because it's the opponents move.
-                    None => {
-                        let Score(big, small) = self.do_minmax(handle.board, recursion - 1).1;
-                        Score(big.invert(), -small)
-                    }
-                };
-                drop(handle);
-                (Some(r#move), result)
+            .map_with(board.clone(), |board, &(r#move, _)| {
+                (r#move, self.search_move(board, limits, r#move, recursion, first_score, Score::MAX))
             })
-            .max_by_key(|(_, v)| *v)
-            .unwrap_or((None, Score(ScoreThing::Score(0), 0)))
+            .max_by_key(|(_, v)| *v);
+
+        // A move that isn't better than the first one only returns an upper bound, so it has to be strictly
+        // better to replace it.
+        let (best_move, best_score) = match best_rest {
+            Some((r#move, score)) if score > first_score => (r#move, score),
+            _ => (first_move, first_score),
+        };
+
+        if limits.is_stopped() {
+            return (Some(best_move), best_score);
+        }
+
+        self.table.store(Entry {
+            hash: board.hash,
+            depth: recursion,
+            bound: Bound::Exact,
+            best_move: Some(best_move),
+            score: best_score,
+        });
+
+        (Some(best_move), best_score)
     }
 }
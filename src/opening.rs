@@ -0,0 +1,124 @@
+//! Opening rules, which decide how the first stones of a game are placed and who plays which color. Without them
+//! the first player has a big advantage.
+
+use crate::{Ai, GameResult};
+use crate::board::{Board, Move, Player};
+use crate::record::RecordedMove;
+use std::time::Instant;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Opening {
+    /// The first player just starts.
+    #[default]
+    Free,
+    /// The first stone has to be in the center, and the third stone at least 3 intersections away from it.
+    Pro,
+    /// Like `Pro`, but the third stone has to be at least 4 intersections away from the center.
+    LongPro,
+    /// The first player places three stones, two of the first color and one of the second, then the other player
+    /// picks which color to play.
+    Swap,
+    /// Like `Swap`, but instead of picking a color the other player can place two more stones, one of each
+    /// color, and let the first player pick.
+    Swap2,
+}
+
+/// What the second player does after the first three stones of a swap2 opening.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Swap2Choice {
+    /// Play as this color.
+    Color(Player),
+    /// Place two more stones and let the opponent pick the color.
+    PlaceTwo,
+}
+
+impl Opening {
+    /// How far from the center the third stone has to be, for the openings that restrict it. The distance is
+    /// counted in intersections along the rows, columns and diagonals.
+    pub fn third_stone_distance(self) -> Option<i32> {
+        match self {
+            Self::Pro => Some(3),
+            Self::LongPro => Some(4),
+            Self::Free | Self::Swap | Self::Swap2 => None,
+        }
+    }
+
+    /// Plays the part of the opening where the players place stones for each other and pick colors. Returns which
+    /// color `player_a` plays as, or how the match ended if someone didn't make a decision or placed stones that
+    /// weren't valid. The placed stones are added to `record`.
+    pub fn play(
+        self,
+        board: &mut Board,
+        player_a: &dyn Ai,
+        player_b: &dyn Ai,
+        record: &mut Vec<RecordedMove>,
+        print_debugging: bool,
+    ) -> Result<Player, GameResult> {
+        match self {
+            Self::Free | Self::Pro | Self::LongPro => Ok(Player::A),
+            Self::Swap => {
+                place_stones(board, player_a, Player::A, 3, record, print_debugging)?;
+                let color = player_b.choose_color(board).ok_or(GameResult::Forfeit(Player::B))?;
+                Ok(color.rotate())
+            }
+            Self::Swap2 => {
+                place_stones(board, player_a, Player::A, 3, record, print_debugging)?;
+                match player_b.choose_swap2(board).ok_or(GameResult::Forfeit(Player::B))? {
+                    Swap2Choice::Color(color) => Ok(color.rotate()),
+                    Swap2Choice::PlaceTwo => {
+                        place_stones(board, player_b, Player::B, 2, record, print_debugging)?;
+                        player_a.choose_color(board).ok_or(GameResult::Forfeit(Player::A))
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Asks `ai`, which is `player` of the match, for `count` opening stones and places them on the board. The time it
+/// took is split evenly between the stones in the record.
+fn place_stones(
+    board: &mut Board,
+    ai: &dyn Ai,
+    player: Player,
+    count: usize,
+    record: &mut Vec<RecordedMove>,
+    print_debugging: bool,
+) -> Result<(), GameResult> {
+    let start = Instant::now();
+    let moves = ai.place_opening(board, count).ok_or(GameResult::Forfeit(player))?;
+    let time = start.elapsed() / count.max(1) as u32;
+
+    for &r#move in moves.iter().take(count) {
+        if let Err(error) = board.try_do_move(r#move) {
+            return Err(GameResult::IllegalMove { culprit: player, r#move, error });
+        }
+
+        record.push(RecordedMove { r#move, time });
+        if print_debugging {
+            println!("{} placed {:?}", ai.name(), r#move);
+        }
+    }
+
+    if moves.len() != count {
+        return Err(GameResult::Forfeit(player));
+    }
+
+    Ok(())
+}
+
+/// Places opening stones by letting `ai` pick moves for both colors on a copy of the board. This is what `Ai`s that
+/// don't have a better idea do.
+pub fn place_with_pick_move(ai: &(impl Ai + ?Sized), board: &Board, count: usize) -> Option<Vec<Move>> {
+    let mut scratch = board.clone();
+    let mut moves = Vec::with_capacity(count);
+    for _ in 0..count {
+        let r#move = ai.pick_move(&mut scratch)?;
+        moves.push(r#move);
+        // An invalid move is left for the caller to reject.
+        if scratch.try_do_move(r#move).is_err() {
+            break;
+        }
+    }
+    Some(moves)
+}
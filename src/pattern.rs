@@ -0,0 +1,314 @@
+//! The shapes stones make along a line, like open threes and fours. Every pattern of a line around a stone is
+//! classified ahead of time, so finding the shape of a stone is a single lookup.
+
+use crate::board::Player;
+use crate::rules::{LineCell, Rule};
+use std::sync::{Arc, Mutex};
+
+/// The longest win length that gets pattern tables. The tables have `3^(2 * win_length + 1)` entries, so they
+/// get big quickly. Caro tables tell the edge of the board apart from opponent stones, so they have
+/// `4^(2 * win_length + 1)` entries and stop one win length earlier.
+pub const MAX_PATTERN_WIN_LENGTH: i32 = 6;
+
+/// The number of different shapes.
+pub const SHAPE_COUNT: usize = 7;
+
+/// How many stones of a player are part of each shape, indexed by `Shape`. A stone is counted once for every
+/// direction, so an open three of three stones adds three to the open threes.
+pub type ShapeCounts = [i32; SHAPE_COUNT];
+
+/// A shape of stones along a line, from weakest to strongest. Fours are one stone away from a five, threes from a
+/// four and twos from a three. The open ones can be completed in two different places, so for fours they can't
+/// be blocked anymore.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Shape {
+    Two,
+    OpenTwo,
+    Three,
+    OpenThree,
+    Four,
+    OpenFour,
+    Five,
+}
+
+impl Shape {
+    pub const ALL: [Shape; SHAPE_COUNT] = [
+        Self::Two,
+        Self::OpenTwo,
+        Self::Three,
+        Self::OpenThree,
+        Self::Four,
+        Self::OpenFour,
+        Self::Five,
+    ];
+
+    /// The shape a line had before the last stone of it was placed, if it's still worth counting.
+    fn weaken(self) -> Option<Self> {
+        match self {
+            Self::OpenFour => Some(Self::OpenThree),
+            Self::Four => Some(Self::Three),
+            Self::OpenThree => Some(Self::OpenTwo),
+            Self::Three => Some(Self::Two),
+            Self::Two | Self::OpenTwo | Self::Five => None,
+        }
+    }
+}
+
+/// The shapes of every line pattern around a stone of one player, for one win length and rule. A pattern is the
+/// `win_length` tiles on each side of the stone, which is one more than a five through the stone can reach so that
+/// the tiles at the ends of it are known too.
+pub struct PatternTable {
+    win_length: i32,
+    /// The number of different tiles. The edge only gets its own digit if the rule needs it.
+    radix: u32,
+    /// For a bitset of tiles, their number in base `radix` with a 1 for every set bit.
+    digits: Vec<u32>,
+    /// The shape of every pattern plus one, or zero if it doesn't have one.
+    shapes: Vec<u8>,
+}
+
+/// The win length, rule and player a table is for.
+type TableKey = (i32, Rule, Player);
+
+/// The tables that were built so far, they're the same for every board with the same settings.
+static TABLES: Mutex<Vec<(TableKey, Arc<PatternTable>)>> = Mutex::new(Vec::new());
+
+impl PatternTable {
+    /// The table for `player` with a win length and rule, or `None` if the win length is longer than
+    /// `MAX_PATTERN_WIN_LENGTH`.
+    pub fn get(win_length: i32, rule: Rule, player: Player) -> Option<Arc<Self>> {
+        let max_win_length = match radix(rule) {
+            3 => MAX_PATTERN_WIN_LENGTH,
+            _ => MAX_PATTERN_WIN_LENGTH - 1,
+        };
+        if !(1..=max_win_length).contains(&win_length) {
+            return None;
+        }
+
+        let key = (win_length, rule, player);
+        let mut tables = TABLES.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some((_, table)) = tables.iter().find(|(table_key, _)| *table_key == key) {
+            return Some(table.clone());
+        }
+
+        let table = Arc::new(Self::build(win_length, rule, player));
+        tables.push((key, table.clone()));
+        Some(table)
+    }
+
+    /// The number of tiles in a pattern.
+    pub fn length(&self) -> u32 {
+        (self.win_length * 2 + 1) as u32
+    }
+
+    /// The shape of a stone, given the tiles around it with bit `i` being `i - win_length` steps away from
+    /// it. `edge` are the tiles outside the board.
+    #[inline]
+    pub fn shape(&self, own: u64, opponent: u64, edge: u64) -> Option<Shape> {
+        // Without a digit of its own, the edge is the same digit as an opponent stone.
+        let index = self.digits[own as usize]
+            + 2 * self.digits[opponent as usize]
+            + (self.radix - 1) * self.digits[edge as usize];
+        match self.shapes[index as usize] {
+            0 => None,
+            shape => Some(Shape::ALL[shape as usize - 1]),
+        }
+    }
+
+    fn build(win_length: i32, rule: Rule, player: Player) -> Self {
+        let length = (win_length * 2 + 1) as usize;
+        let radix = radix(rule);
+        let digits = (0..1u32 << length)
+            .map(|bits| (0..length).filter(|&i| bits & (1 << i) != 0).map(|i| radix.pow(i as u32)).sum())
+            .collect();
+
+        let mut builder = Builder {
+            win_length,
+            rule,
+            player,
+            radix: radix as usize,
+            line: vec![LineCell::Empty; length],
+            shapes: vec![None; (radix as usize).pow(length as u32)],
+        };
+        for index in builder.possible_patterns() {
+            builder.shape(index);
+        }
+
+        Self {
+            win_length,
+            radix,
+            digits,
+            shapes: builder
+                .shapes
+                .into_iter()
+                .map(|shape| shape.flatten().map_or(0, |shape| shape as u8 + 1))
+                .collect(),
+        }
+    }
+}
+
+struct Builder {
+    win_length: i32,
+    rule: Rule,
+    player: Player,
+    radix: usize,
+    /// Scratch space for the pattern being looked at.
+    line: Vec<LineCell>,
+    /// The shapes found so far, `None` if the pattern hasn't been looked at yet.
+    shapes: Vec<Option<Option<Shape>>>,
+}
+
+impl Builder {
+    fn center(&self) -> usize {
+        self.win_length as usize
+    }
+
+    /// The digit of tile `i` of a pattern. Tiles are 0 if empty, 1 for an own stone, 2 for an opponent stone and
+    /// 3 for the edge of the board.
+    fn digit(&self, index: usize, i: usize) -> usize {
+        index / self.radix.pow(i as u32) % self.radix
+    }
+
+    /// The patterns that can be seen on a board, where the edge is only at the ends and the stone in the middle
+    /// is on the board. Adding stones to one of them gives another one, so the others are never looked at.
+    fn possible_patterns(&self) -> Vec<usize> {
+        let length = self.line.len();
+        let max_edge = if self.radix > 3 { self.center() } else { 0 };
+
+        let mut patterns = Vec::new();
+        for before in 0..=max_edge {
+            for after in 0..=max_edge {
+                let tiles = before..length - after;
+                let edge: usize = (0..length).filter(|i| !tiles.contains(i)).map(|i| 3 * self.radix.pow(i as u32)).sum();
+                for digits in 0..3usize.pow(tiles.len() as u32) {
+                    let stones: usize = tiles
+                        .clone()
+                        .enumerate()
+                        .map(|(j, i)| digits / 3usize.pow(j as u32) % 3 * self.radix.pow(i as u32))
+                        .sum();
+                    patterns.push(edge + stones);
+                }
+            }
+        }
+        patterns
+    }
+
+    /// The shape of the stone in the middle of a pattern, where every tile is a digit in base `radix`. Shapes are
+    /// found by seeing what adding stones to the pattern makes, so it recurses into patterns with more stones.
+    fn shape(&mut self, index: usize) -> Option<Shape> {
+        if let Some(shape) = self.shapes[index] {
+            return shape;
+        }
+
+        let length = self.line.len();
+        let shape = if self.digit(index, self.center()) != 1 {
+            None
+        } else if self.is_five(index) {
+            Some(Shape::Five)
+        } else {
+            let empty: Vec<usize> = (0..length).filter(|&i| self.digit(index, i) == 0).collect();
+            let completions = empty.iter().filter(|&&i| self.is_five(index + self.radix.pow(i as u32))).count();
+            match completions {
+                0 => empty
+                    .iter()
+                    .filter_map(|&i| self.shape(index + self.radix.pow(i as u32)).and_then(Shape::weaken))
+                    .max(),
+                1 => Some(Shape::Four),
+                _ => Some(Shape::OpenFour),
+            }
+        };
+
+        self.shapes[index] = Some(shape);
+        shape
+    }
+
+    /// Returns true if the stone in the middle of the pattern is part of a winning line. A line that reaches the
+    /// edge of the pattern is longer than the win length, and the tile past it is treated as empty, as is the edge
+    /// of the board.
+    fn is_five(&mut self, index: usize) -> bool {
+        for i in 0..self.line.len() {
+            self.line[i] = match self.digit(index, i) {
+                0 => LineCell::Empty,
+                1 => LineCell::Own,
+                _ => LineCell::Blocked,
+            };
+        }
+
+        let center = self.center();
+        let start = self.line[..center].iter().rposition(|&cell| cell != LineCell::Own).map_or(0, |i| i + 1);
+        let end = self.line[center..].iter().position(|&cell| cell != LineCell::Own).map_or(self.line.len(), |i| center + i);
+        let tile = |i: Option<usize>| match i.filter(|&i| i < self.line.len()).map(|i| self.digit(index, i)) {
+            Some(2) => Some(self.player.rotate()),
+            _ => None,
+        };
+        let ends = [tile(start.checked_sub(1)), tile(Some(end))];
+        self.rule.is_winning_line(self.player, (end - start) as i32, ends, self.win_length)
+    }
+}
+
+/// The number of different tiles in the patterns of a rule. Only caro needs to tell the edge of the board apart
+/// from opponent stones, since they block a five there and the edge doesn't.
+fn radix(rule: Rule) -> u32 {
+    match rule {
+        Rule::Caro => 4,
+        Rule::Freestyle | Rule::Renju | Rule::Standard => 3,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PatternScore, ScoreThing, ScoringFunction};
+    use crate::board::Board;
+    use crate::random::Rng;
+
+    fn shapes(notation: &str, player: Player) -> Vec<(Shape, i32)> {
+        let counts = Board::from_notation(notation).unwrap().shape_counts(player);
+        Shape::ALL.iter().zip(counts.iter()).filter(|(_, &count)| count > 0).map(|(&shape, &count)| (shape, count)).collect()
+    }
+
+    #[test]
+    fn classifies_lines() {
+        assert_eq!(shapes("9/1xxx5/9 o freestyle 5", Player::A), [(Shape::OpenThree, 3)]);
+        assert_eq!(shapes("9/xxx6/9 o freestyle 5", Player::A), [(Shape::Three, 3)]);
+        assert_eq!(shapes("9/1xx1x4/9 o freestyle 5", Player::A), [(Shape::OpenThree, 3)]);
+        assert_eq!(shapes("9/1xxxx4/9 o freestyle 5", Player::A), [(Shape::OpenFour, 4)]);
+        assert_eq!(shapes("9/oxxxx4/9 o freestyle 5", Player::A), [(Shape::Four, 4)]);
+        assert_eq!(shapes("9/1xx6/9 o freestyle 5", Player::A), [(Shape::OpenTwo, 2)]);
+
+        // In caro the edge doesn't block a five, only opponent stones on both ends do.
+        assert_eq!(shapes("xxxx1o4/10/10/10/o9 x caro 5", Player::A), [(Shape::Four, 4)]);
+        assert!(shapes("oxxxx1o3/10/10/10/10 x caro 5", Player::A).iter().all(|&(shape, _)| shape < Shape::Four));
+        assert_eq!(shapes("10/1xxxx5/10 o caro 5", Player::A), [(Shape::OpenFour, 4)]);
+    }
+
+    #[test]
+    fn overlines_are_not_fives() {
+        // Filling the gap makes six in a row, which only wins in freestyle.
+        let notation = |rule| format!("xxxx1x9/15/15/o14/o14 x {} 5", rule);
+        assert_eq!(shapes(&notation("freestyle"), Player::A), [(Shape::Four, 5)]);
+        for rule in ["standard", "renju"] {
+            assert!(shapes(&notation(rule), Player::A).iter().all(|&(shape, _)| shape < Shape::Four), "{}", rule);
+
+            let mut board = Board::from_notation(&notation(rule)).unwrap();
+            assert_ne!(PatternScore::default().score(&mut board).0, ScoreThing::Max, "{}", rule);
+        }
+    }
+
+    #[test]
+    fn counts_follow_the_game() {
+        let rng = Rng::new(3);
+        for rule in ["freestyle", "renju", "standard", "caro"] {
+            let mut board = Board::from_notation(&format!("9/9/9/9/9/9/9/9/9 x {} 4", rule)).unwrap();
+            while board.won.is_none() && !board.is_draw() {
+                let moves: Vec<_> = board.get_moves().collect();
+                board.do_move(moves[rng.below(moves.len() as u64) as usize]);
+
+                let loaded = Board::from_notation(&board.to_notation()).unwrap();
+                for player in [Player::A, Player::B] {
+                    assert_eq!(board.shape_counts(player), loaded.shape_counts(player), "{}", board.to_notation());
+                }
+            }
+        }
+    }
+}
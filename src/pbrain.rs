@@ -0,0 +1,340 @@
+//! The piskvork "pbrain" protocol, which gomocup tournaments and the piskvork manager use to talk to engines over
+//! stdin and stdout.
+//!
+//! The manager sends commands like `START 15`, `TURN 7,7` and `BOARD`, and the engine answers with `OK`, a move as
+//! `x,y`, or `ERROR message`. Coordinates start at zero in the top left corner.
+
+use crate::Ai;
+use crate::bitboard::MAX_LINE_LENGTH;
+use crate::board::{Board, Move, Player, DEFAULT_WIN_LENGTH};
+use crate::rules::Rule;
+use crate::time_control::TimeControl;
+use glam::{IVec2, ivec2};
+use std::io::{self, BufRead, Write};
+use std::time::Duration;
+
+/// Time kept in reserve on every move, for the overhead of the protocol and the manager.
+const SAFETY_MARGIN: Duration = Duration::from_millis(50);
+
+const ABOUT: &str = r#"name="femirad", version="0.1", author="TrolledWoods""#;
+
+/// The state of a game played over the protocol.
+pub struct Brain<T> {
+    ai: T,
+    board: Option<Board>,
+    rule: Rule,
+    timeout_turn: Option<Duration>,
+    time_left: Option<Duration>,
+    /// The stones received so far while reading a `BOARD` command.
+    reading_board: Option<Vec<(IVec2, Player)>>,
+}
+
+impl<T> Brain<T> where T: Ai {
+    pub fn new(ai: T) -> Self {
+        Self {
+            ai,
+            board: None,
+            rule: Rule::Freestyle,
+            timeout_turn: None,
+            time_left: None,
+            reading_board: None,
+        }
+    }
+
+    /// Handles one line from the manager and returns the lines to send back. Returns `None` when the manager
+    /// wants the engine to quit.
+    pub fn handle_line(&mut self, line: &str) -> Option<Vec<String>> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Some(Vec::new());
+        }
+
+        if self.reading_board.is_some() {
+            return Some(self.board_line(line));
+        }
+
+        let (command, arguments) = match line.find(' ') {
+            Some(index) => (&line[..index], line[index + 1..].trim()),
+            None => (line, ""),
+        };
+
+        let response = match command.to_ascii_uppercase().as_str() {
+            "START" => match arguments.parse::<usize>() {
+                Ok(size) => self.start(size, size),
+                Err(_) => vec![format!("ERROR invalid board size {:?}", arguments)],
+            },
+            "RECTSTART" => match parse_pair(arguments) {
+                Some((width, height)) if width > 0 && height > 0 => self.start(width as usize, height as usize),
+                _ => vec![format!("ERROR invalid board size {:?}", arguments)],
+            },
+            "RESTART" => match &self.board {
+                Some(board) => {
+                    let (width, height) = (board.width(), board.height());
+                    self.start(width, height)
+                }
+                None => vec!["ERROR no game has been started".to_string()],
+            },
+            "BEGIN" => self.play(),
+            "TURN" => match parse_pair(arguments) {
+                Some((x, y)) => self.opponent_move(ivec2(x, y)),
+                None => vec![format!("ERROR invalid move {:?}", arguments)],
+            },
+            "BOARD" => {
+                if self.board.is_none() {
+                    vec!["ERROR no game has been started".to_string()]
+                } else {
+                    self.reading_board = Some(Vec::new());
+                    Vec::new()
+                }
+            }
+            "INFO" => {
+                self.info(arguments);
+                Vec::new()
+            }
+            "ABOUT" => vec![ABOUT.to_string()],
+            "END" => return None,
+            _ => vec!["UNKNOWN".to_string()],
+        };
+
+        Some(response)
+    }
+
+    fn start(&mut self, width: usize, height: usize) -> Vec<String> {
+        if width < DEFAULT_WIN_LENGTH as usize && height < DEFAULT_WIN_LENGTH as usize {
+            return vec![format!("ERROR a {}x{} board is too small", width, height)];
+        }
+
+        if width > MAX_LINE_LENGTH || height > MAX_LINE_LENGTH {
+            return vec![format!("ERROR a {}x{} board is too big", width, height)];
+        }
+
+        self.board = Some(Board::new(width, height, DEFAULT_WIN_LENGTH).with_rule(self.rule));
+        vec!["OK".to_string()]
+    }
+
+    fn info(&mut self, arguments: &str) {
+        let mut parts = arguments.split_whitespace();
+        let key = parts.next().unwrap_or("");
+        let value = match parts.next().and_then(|value| value.parse::<u64>().ok()) {
+            Some(value) => value,
+            None => return,
+        };
+
+        match key {
+            // Zero means that there's no limit.
+            "timeout_turn" => self.timeout_turn = Some(Duration::from_millis(value)).filter(|time| !time.is_zero()),
+            "time_left" => self.time_left = Some(Duration::from_millis(value)),
+            // Zero means that there's no limit.
+            "max_memory" if value > 0 => self.ai.set_memory_limit(value),
+            "rule" => {
+                self.rule = if value & 4 != 0 {
+                    Rule::Renju
+                } else if value & 8 != 0 {
+                    Rule::Caro
+                } else if value & 1 != 0 {
+                    Rule::Standard
+                } else {
+                    Rule::Freestyle
+                };
+
+                // The rule can only be changed before the game has begun.
+                if let Some(board) = &self.board {
+                    if board.moves == 0 {
+                        self.board = Some(Board::new(board.width(), board.height(), board.win_length()).with_rule(self.rule));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn opponent_move(&mut self, pos: IVec2) -> Vec<String> {
+        let board = match &mut self.board {
+            Some(board) => board,
+            None => return vec!["ERROR no game has been started".to_string()],
+        };
+
+        let r#move = Move { pos, player: board.current_player };
+        if let Err(error) = board.try_do_move(r#move) {
+            return vec![format!("ERROR invalid move, {}", error)];
+        }
+
+        self.play()
+    }
+
+    fn board_line(&mut self, line: &str) -> Vec<String> {
+        if line.eq_ignore_ascii_case("DONE") {
+            let stones = self.reading_board.take().unwrap_or_default();
+            return self.set_up_board(&stones);
+        }
+
+        let mut parts = line.split(',').map(|part| part.trim().parse::<i32>());
+        let stone = match (parts.next(), parts.next(), parts.next()) {
+            (Some(Ok(x)), Some(Ok(y)), Some(Ok(1))) => (ivec2(x, y), Player::A),
+            (Some(Ok(x)), Some(Ok(y)), Some(Ok(2))) => (ivec2(x, y), Player::B),
+            // Only sent in continuous games, which aren't supported, so they're skipped.
+            (Some(Ok(_)), Some(Ok(_)), Some(Ok(3))) => return Vec::new(),
+            _ => return vec![format!("ERROR invalid board line {:?}", line)],
+        };
+
+        if let Some(stones) = &mut self.reading_board {
+            stones.push(stone);
+        }
+        Vec::new()
+    }
+
+    /// Sets up a board sent with the `BOARD` command and plays a move on it. The stones come marked as ours
+    /// (`Player::A` here) and the opponent's (`Player::B` here), and which color we are depends on who has more
+    /// stones.
+    fn set_up_board(&mut self, stones: &[(IVec2, Player)]) -> Vec<String> {
+        let board = match &self.board {
+            Some(board) => board,
+            None => return vec!["ERROR no game has been started".to_string()],
+        };
+
+        let ours = stones.iter().filter(|(_, owner)| *owner == Player::A).count();
+        let own_color = if ours * 2 == stones.len() { Player::A } else { Player::B };
+
+        let mut new_board = Board::new(board.width(), board.height(), board.win_length()).with_rule(self.rule);
+        for &(pos, owner) in stones {
+            let player = if owner == Player::A { own_color } else { own_color.rotate() };
+            if new_board.get(pos) != Some(None) {
+                return vec![format!("ERROR invalid stone {},{}", pos.x, pos.y)];
+            }
+            new_board.do_move(Move { pos, player });
+        }
+
+        self.board = Some(new_board);
+        self.play()
+    }
+
+    /// Lets the `Ai` pick a move, plays it, and returns it in the format of the protocol.
+    fn play(&mut self) -> Vec<String> {
+        let budget = match (self.timeout_turn, self.time_left) {
+            (Some(turn), Some(left)) => {
+                Some(turn.min(TimeControl::Clock { remaining: left, increment: Duration::ZERO }.move_budget()))
+            }
+            (Some(turn), None) => Some(turn),
+            (None, Some(left)) => Some(TimeControl::Clock { remaining: left, increment: Duration::ZERO }.move_budget()),
+            (None, None) => None,
+        };
+
+        if let Some(budget) = budget {
+            self.ai.set_time_control(TimeControl::PerMove(budget.saturating_sub(SAFETY_MARGIN)));
+        }
+
+        let board = match &mut self.board {
+            Some(board) => board,
+            None => return vec!["ERROR no game has been started".to_string()],
+        };
+
+        if board.won.is_some() || board.is_draw() {
+            return vec!["ERROR the game is over".to_string()];
+        }
+
+        match self.ai.pick_move(board).map(|r#move| board.try_do_move(r#move).map(|_| r#move)) {
+            Some(Ok(r#move)) => vec![format!("{},{}", r#move.pos.x, r#move.pos.y)],
+            Some(Err(error)) => vec![format!("ERROR the engine picked an invalid move, {}", error)],
+            None => vec!["ERROR the engine could not find a move".to_string()],
+        }
+    }
+}
+
+fn parse_pair(string: &str) -> Option<(i32, i32)> {
+    let mut parts = string.split(',').map(|part| part.trim().parse::<i32>().ok());
+    let pair = (parts.next()??, parts.next()??);
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(pair)
+}
+
+/// Speaks the protocol with a manager until it sends `END` or closes the input.
+pub fn run(ai: impl Ai, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+    let mut brain = Brain::new(ai);
+    for line in input.lines() {
+        match brain.handle_line(&line?) {
+            Some(response) => {
+                for line in response {
+                    writeln!(output, "{}", line)?;
+                }
+                output.flush()?;
+            }
+            None => break,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Plays the first valid move, so that the answers are known ahead of time.
+    #[derive(Default)]
+    struct FirstMove {
+        memory_limit: Option<u64>,
+    }
+
+    impl Ai for FirstMove {
+        fn name(&self) -> &str {
+            "First move"
+        }
+
+        fn set_memory_limit(&mut self, bytes: u64) {
+            self.memory_limit = Some(bytes);
+        }
+
+        fn pick_move(&self, board: &mut Board) -> Option<Move> {
+            board.get_moves().next()
+        }
+    }
+
+    #[test]
+    fn scripted_session() {
+        let script = "START 15\n\
+            INFO timeout_turn 1000\n\
+            INFO max_memory 83886080\n\
+            BEGIN\n\
+            TURN 1,0\n\
+            BOARD\n\
+            5,5,1\n\
+            6,6,2\n\
+            DONE\n\
+            TURN 20,20\n\
+            ABOUT\n\
+            FOO\n\
+            END\n\
+            BEGIN\n";
+        let mut output = Vec::new();
+        run(FirstMove::default(), script.as_bytes(), &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 7, "{:?}", lines);
+        assert_eq!(lines[0], "OK");
+        assert_eq!(lines[1], "0,0");
+        assert_eq!(lines[2], "2,0");
+        assert_eq!(lines[3], "0,0");
+        assert!(lines[4].starts_with("ERROR invalid move"), "{:?}", lines[4]);
+        assert_eq!(lines[5], ABOUT);
+        assert_eq!(lines[6], "UNKNOWN");
+    }
+
+    #[test]
+    fn max_memory_is_passed_on() {
+        let mut brain = Brain::new(FirstMove::default());
+        brain.handle_line("INFO max_memory 0");
+        assert_eq!(brain.ai.memory_limit, None);
+        brain.handle_line("INFO max_memory 1000000");
+        assert_eq!(brain.ai.memory_limit, Some(1_000_000));
+    }
+
+    #[test]
+    fn board_sizes() {
+        let mut brain = Brain::new(FirstMove::default());
+        assert_eq!(brain.handle_line("START 40"), Some(vec!["OK".to_string()]));
+        assert_eq!(brain.handle_line("START 64"), Some(vec!["OK".to_string()]));
+        assert_eq!(brain.handle_line("START 65"), Some(vec!["ERROR a 65x65 board is too big".to_string()]));
+    }
+}
@@ -0,0 +1,610 @@
+//! Depth-first proof-number search, which finds the game-theoretic value of a position instead of a heuristic
+//! score. It's only practical on small boards, or when one side has a forced win.
+//!
+//! A search tries to prove that the attacker wins. Every node has a proof number, the number of leaves that would
+//! have to be wins to prove it, and a disproof number, the number of leaves that would have to be draws or losses
+//! to disprove it. The search always expands the most proving node, and keeps the numbers of the positions it has
+//! seen in a table keyed by the zobrist hash of the board. Positions that are the same up to a rotation or a
+//! reflection of the board share an entry.
+//!
+//! Proving a draw means disproving a win for both players, which takes far more nodes than finding a win. A
+//! position is disproven as soon as the defender can be sure to stop every line the attacker could still fill,
+//! which is often long before the board is full.
+
+use crate::board::{Board, Move, MoveFilter, Player, Tile, DIRECTIONS};
+use crate::rules::Rule;
+use crate::zobrist;
+use glam::{ivec2, IVec2};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::hash::{BuildHasherDefault, Hasher};
+
+/// Proof and disproof numbers at or above this are infinite.
+const INFINITY: u32 = u32::MAX / 2;
+
+/// The keys of the table are zobrist hashes, which are already random, so they are used as they are.
+#[derive(Default)]
+struct HashHasher(u64);
+
+impl Hasher for HashHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, _bytes: &[u8]) {
+        unreachable!("Only zobrist hashes are hashed");
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.0 = value;
+    }
+}
+
+/// The proof and disproof numbers of a position.
+type Numbers = (u32, u32);
+
+/// What the table knows about a position.
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    numbers: Numbers,
+    /// The number of nodes it took to find the numbers. When the table is full, the entries that took the least
+    /// work are thrown away first, since they're the cheapest to find again.
+    work: u64,
+}
+
+type Table = HashMap<u64, Entry, BuildHasherDefault<HashHasher>>;
+
+/// A rotation or reflection of the board.
+#[derive(Debug, Clone, Copy)]
+struct Symmetry {
+    transpose: bool,
+    mirror_x: bool,
+    mirror_y: bool,
+}
+
+impl Symmetry {
+    /// The symmetries of a board. Only square boards can be transposed. Openings that restrict the moves around
+    /// the center aren't symmetric on boards with an even size, so with those only the board itself is used.
+    fn of(board: &Board) -> Vec<Self> {
+        if board.opening().third_stone_distance().is_some() {
+            return vec![Self { transpose: false, mirror_x: false, mirror_y: false }];
+        }
+
+        let transposes: &[bool] = if board.width() == board.height() { &[false, true] } else { &[false] };
+        let mut symmetries = Vec::new();
+        for &transpose in transposes {
+            for mirror_x in [false, true] {
+                for mirror_y in [false, true] {
+                    symmetries.push(Self { transpose, mirror_x, mirror_y });
+                }
+            }
+        }
+        symmetries
+    }
+
+    fn apply(self, pos: IVec2, board: &Board) -> IVec2 {
+        let pos = if self.transpose { ivec2(pos.y, pos.x) } else { pos };
+        ivec2(
+            if self.mirror_x { board.width() as i32 - 1 - pos.x } else { pos.x },
+            if self.mirror_y { board.height() as i32 - 1 - pos.y } else { pos.y },
+        )
+    }
+}
+
+/// The value of a position for the player to move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Solution {
+    /// The player to move wins by playing the move.
+    Win(Move),
+    /// Neither player can force a win. The move keeps the draw, there is none if the board is full.
+    Draw(Option<Move>),
+    /// The opponent wins whatever the player to move does. The move is one of the moves that lose.
+    Loss(Option<Move>),
+    /// The search ran out of nodes or memory.
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ProofSolver {
+    /// The number of positions to expand before giving up.
+    pub node_limit: u64,
+    /// The number of positions the table can hold. When it fills up, the half of the positions that took the
+    /// least work to find is thrown away.
+    pub table_limit: usize,
+    /// Which moves are tried, unless a move is forced. Only trying moves close to the stones makes the search a
+    /// lot faster on big boards, but the result is only exact if the moves further away are bad.
+    pub move_filter: MoveFilter,
+}
+
+impl Default for ProofSolver {
+    fn default() -> Self {
+        Self {
+            node_limit: 1_000_000,
+            table_limit: 1 << 20,
+            move_filter: MoveFilter::All,
+        }
+    }
+}
+
+/// The state of a single search, for one attacker.
+struct Search<'a> {
+    solver: &'a ProofSolver,
+    attacker: Player,
+    symmetries: Vec<Symmetry>,
+    table: Table,
+    nodes: u64,
+    aborted: bool,
+}
+
+impl ProofSolver {
+    pub fn new(node_limit: u64, table_limit: usize) -> Self {
+        Self {
+            node_limit,
+            table_limit,
+            move_filter: MoveFilter::All,
+        }
+    }
+
+    pub fn with_move_filter(mut self, move_filter: MoveFilter) -> Self {
+        self.move_filter = move_filter;
+        self
+    }
+
+    /// Finds the value of the board for the player to move. This first tries to prove a win for the player to move,
+    /// and if that fails a win for the opponent.
+    pub fn solve(&self, board: &mut Board) -> Solution {
+        let player = board.current_player;
+        if board.won.is_some() {
+            return Solution::Loss(None);
+        }
+
+        let mut search = self.search(player, board);
+        let (proof, disproof) = search.run(board);
+        if search.aborted {
+            return Solution::Unknown;
+        }
+        if proof == 0 {
+            return match search.best_child(board, |numbers| numbers.0 == 0) {
+                Some(r#move) => Solution::Win(r#move),
+                None => Solution::Unknown,
+            };
+        }
+        debug_assert_eq!(disproof, 0);
+
+        let mut search = self.search(player.rotate(), board);
+        let (proof, _) = search.run(board);
+        if search.aborted {
+            return Solution::Unknown;
+        }
+        if proof == 0 {
+            Solution::Loss(self.moves(board).first().copied())
+        } else {
+            Solution::Draw(search.best_child(board, |numbers| numbers.1 == 0))
+        }
+    }
+
+    fn search(&self, attacker: Player, board: &Board) -> Search<'_> {
+        Search {
+            solver: self,
+            attacker,
+            symmetries: Symmetry::of(board),
+            table: Table::default(),
+            nodes: 0,
+            aborted: false,
+        }
+    }
+
+    /// The moves worth trying on the board, the ones that make or block threats first. A move that wins is the
+    /// only one that matters, and if the opponent could win with their next move the only moves that don't lose
+    /// are the ones that stop it.
+    fn moves(&self, board: &Board) -> Vec<Move> {
+        let player = board.current_player;
+        let to_move = |pos| Move { pos, player };
+
+        if let Some(r#move) = board.winning_cells(player).into_iter().map(to_move).find(|&r#move| board.is_move_valid(r#move)) {
+            return vec![r#move];
+        }
+
+        let threats = board.winning_cells(player.rotate());
+        if !threats.is_empty() {
+            let blocks: Vec<Move> = threats.into_iter().map(to_move).filter(|&r#move| board.is_move_valid(r#move)).collect();
+            // If none of them can be blocked every move loses, so any of them will do.
+            if !blocks.is_empty() {
+                return blocks;
+            }
+        }
+
+        let weights = window_weights(board);
+        let weight = |r#move: &Move| weights[r#move.pos.y as usize * board.width() + r#move.pos.x as usize];
+
+        let mut moves: Vec<Move> = board.get_moves_filtered(self.move_filter).collect();
+        // A tile that isn't in a line either player could still fill is as good as passing. In freestyle more
+        // stones never hurt, so passing is never better than playing somewhere else and those moves can be left
+        // out. In caro a stone at the end of a line can still block it.
+        if board.rule() == Rule::Freestyle && moves.iter().any(|r#move| weight(r#move) > 0) {
+            moves.retain(|r#move| weight(r#move) > 0);
+        }
+        moves.sort_by_key(|r#move| Reverse(weight(r#move)));
+        moves
+    }
+}
+
+impl Search<'_> {
+    /// Searches until the board is proven or disproven, and returns its numbers.
+    fn run(&mut self, board: &mut Board) -> Numbers {
+        self.expand(board, INFINITY, INFINITY);
+        self.numbers(board)
+    }
+
+    /// The zobrist hash of the board as seen through every symmetry.
+    fn hashes(&self, board: &Board) -> Vec<u64> {
+        let side = if board.current_player == Player::B { zobrist::SIDE_KEY } else { 0 };
+        let stones: Vec<(IVec2, Player)> = board.positions().filter_map(|pos| Some((pos, board.get(pos)??))).collect();
+        self.symmetries
+            .iter()
+            .map(|&symmetry| {
+                stones.iter().fold(side, |hash, &(pos, player)| hash ^ zobrist::tile_key(symmetry.apply(pos, board), player))
+            })
+            .collect()
+    }
+
+    /// The key of the board after a move, given the `hashes` of the board before it. Boards that are the same up
+    /// to a symmetry have the same key.
+    fn key_after(&self, hashes: &[u64], board: &Board, r#move: Move) -> u64 {
+        self.symmetries
+            .iter()
+            .zip(hashes)
+            .map(|(&symmetry, hash)| hash ^ zobrist::tile_key(symmetry.apply(r#move.pos, board), r#move.player) ^ zobrist::SIDE_KEY)
+            .min()
+            .unwrap()
+    }
+
+    /// The numbers of the board as far as they are known.
+    fn numbers(&self, board: &Board) -> Numbers {
+        match board.won {
+            Some(winner) if winner == self.attacker => (0, INFINITY),
+            Some(_) => (INFINITY, 0),
+            None => {
+                let key = self.hashes(board).into_iter().min().unwrap();
+                match self.table.get(&key) {
+                    Some(entry) => entry.numbers,
+                    None if !Worth::of(board, self.attacker).can_win(board) => (INFINITY, 0),
+                    None => (1, 1),
+                }
+            }
+        }
+    }
+
+    fn store(&mut self, key: u64, numbers: Numbers, work: u64) {
+        if self.table.len() >= self.solver.table_limit.max(1) && !self.table.contains_key(&key) {
+            let mut works: Vec<u64> = self.table.values().map(|entry| entry.work).collect();
+            let middle = works.len() / 2;
+            let (_, &mut median, _) = works.select_nth_unstable(middle);
+            self.table.retain(|_, entry| entry.work > median);
+        }
+        self.table.insert(key, Entry { numbers, work });
+    }
+
+    /// The child of the board whose numbers match, if there is one.
+    fn best_child(&self, board: &mut Board, matches: impl Fn(Numbers) -> bool) -> Option<Move> {
+        self.solver.moves(board).into_iter().find(|&r#move| {
+            let handle = board.do_reversible_move(r#move);
+            matches(self.numbers(handle.board))
+        })
+    }
+
+    /// Expands the board until its proof number reaches `proof_threshold` or its disproof number reaches
+    /// `disproof_threshold`.
+    fn expand(&mut self, board: &mut Board, proof_threshold: u32, disproof_threshold: u32) {
+        let attacking = board.current_player == self.attacker;
+        let start = self.nodes;
+        let hashes = self.hashes(board);
+        let key = hashes.iter().copied().min().unwrap();
+
+        let worth = Worth::of(board, self.attacker);
+        if !worth.can_win(board) {
+            // The attacker can't win anymore, whatever happens.
+            self.store(key, (INFINITY, 0), 1);
+            return;
+        }
+
+        // The children are looked up by their key, so that the moves don't have to be done every time the numbers
+        // are updated. Children that end the game have fixed numbers. Moves that give the same board up to a
+        // symmetry only need to be searched once.
+        let mut children: Vec<(Move, u64, Option<Numbers>, Numbers)> = Vec::new();
+        for r#move in self.solver.moves(board) {
+            let child = self.key_after(&hashes, board, r#move);
+            if children.iter().any(|&(_, other, _, _)| other == child) {
+                continue;
+            }
+
+            let end = if !board.wins_at(r#move.pos, r#move.player) {
+                None
+            } else if attacking {
+                Some((0, INFINITY))
+            } else {
+                Some((INFINITY, 0))
+            };
+            // The more the lines of the attacker are worth, the harder the board is to disprove, so the disproof
+            // number of a child that hasn't been searched starts out higher.
+            let after = worth.after(board, r#move.pos.y as usize * board.width() + r#move.pos.x as usize);
+            let estimate = (1, 1 + (after as f64 / worth.one as f64).powi(3).min(INFINITY as f64) as u32);
+            children.push((r#move, child, end, estimate));
+        }
+
+        if children.is_empty() {
+            // A full board is a draw, which is as good as a loss for the attacker.
+            self.store(key, (INFINITY, 0), 1);
+            return;
+        }
+
+        loop {
+            self.nodes += 1;
+            if self.nodes > self.solver.node_limit {
+                self.aborted = true;
+            }
+
+            // The numbers of the children, turned around so that the search picks the child with the smallest
+            // `first` and sums the `second`s, whoever is to move.
+            let mut best = (INFINITY + 1, 0);
+            let mut second_best = INFINITY;
+            let mut sum: u32 = 0;
+            let mut best_move = children[0].0;
+            for &(r#move, hash, end, estimate) in &children {
+                let (proof, disproof) = end.or_else(|| self.table.get(&hash).map(|entry| entry.numbers)).unwrap_or(estimate);
+
+                let (first, second) = if attacking { (proof, disproof) } else { (disproof, proof) };
+                sum = sum.saturating_add(second).min(INFINITY);
+                if first < best.0 {
+                    second_best = best.0;
+                    best = (first, second);
+                    best_move = r#move;
+                } else if first < second_best {
+                    second_best = first;
+                }
+            }
+
+            let (proof, disproof) = if attacking { (best.0, sum) } else { (sum, best.0) };
+            self.store(key, (proof, disproof), self.nodes - start + 1);
+
+            if proof >= proof_threshold || disproof >= disproof_threshold || self.aborted {
+                return;
+            }
+
+            let (first_threshold, second_threshold) = if attacking {
+                (proof_threshold, disproof_threshold)
+            } else {
+                (disproof_threshold, proof_threshold)
+            };
+            // Letting the child go a quarter past the second best child before coming back up saves a lot of
+            // switching back and forth between the two.
+            let child_first = first_threshold.min(second_best.saturating_add(second_best / 4 + 1));
+            let child_second = (second_threshold - sum.min(second_threshold - 1)).saturating_add(best.1);
+            let (child_proof, child_disproof) = if attacking {
+                (child_first, child_second)
+            } else {
+                (child_second, child_first)
+            };
+
+            let handle = board.do_reversible_move(best_move);
+            self.expand(handle.board, child_proof.min(INFINITY), child_disproof.min(INFINITY));
+        }
+    }
+}
+
+/// Calls `f` with the player, the empty tiles and the number of stones of the player of every line of the win
+/// length that a player could still fill to win. Lines that need more stones than the player gets to place before
+/// the board is full can't be filled anymore, so they're left out. The tiles are indexed row by row.
+fn for_each_window(board: &Board, mut f: impl FnMut(Player, &[usize], i32)) {
+    let (width, height) = (board.width() as i32, board.height() as i32);
+    let win_length = board.win_length();
+    let empty_tiles = (board.width() * board.height() - board.moves) as i32;
+    let stones_left = |player| if board.current_player == player { (empty_tiles + 1) / 2 } else { empty_tiles / 2 };
+
+    // Reading the tiles into a list first is a lot faster than going through the board for every line.
+    let tiles: Vec<Tile> = board.positions().map(|pos| board.get(pos).flatten()).collect();
+    let on_board = |x: i32, y: i32| (0..width).contains(&x) && (0..height).contains(&y);
+    let tile = |x: i32, y: i32| if on_board(x, y) { tiles[(y * width + x) as usize] } else { None };
+
+    let mut empty = Vec::with_capacity(win_length as usize);
+    for y in 0..height {
+        for x in 0..width {
+            for &direction in DIRECTIONS.iter() {
+                let (dx, dy) = (direction.x, direction.y);
+                if !on_board(x + dx * (win_length - 1), y + dy * (win_length - 1)) {
+                    continue;
+                }
+
+                let mut stones = [0; 2];
+                empty.clear();
+                for i in 0..win_length {
+                    let index = ((y + dy * i) * width + x + dx * i) as usize;
+                    match tiles[index] {
+                        Some(owner) => stones[owner as usize] += 1,
+                        None => empty.push(index),
+                    }
+                }
+
+                for player in [Player::A, Player::B] {
+                    let own = stones[player as usize];
+                    if stones[player.rotate() as usize] > 0 || own + stones_left(player) < win_length {
+                        continue;
+                    }
+
+                    let before = tile(x - dx, y - dy);
+                    let after = tile(x + dx * win_length, y + dy * win_length);
+                    if board.rule().can_win_window(player, before, after) {
+                        f(player, &empty, own);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// For every tile, how much the lines through it that either player could still fill to win are worth. A line is
+/// worth four times as much for every stone in it, so the tiles that make or block threats come first.
+fn window_weights(board: &Board) -> Vec<u64> {
+    let mut weights = vec![0_u64; board.width() * board.height()];
+    for_each_window(board, |_, empty, stones| {
+        let weight = 4_u64.saturating_pow(stones as u32);
+        for &tile in empty {
+            weights[tile] = weights[tile].saturating_add(weight);
+        }
+    });
+    weights
+}
+
+/// What the lines `player` could still fill to win are worth. By the Erdős–Selfridge theorem, a line missing `n`
+/// stones is worth `2^-n`, and if the lines are worth less than one in total with the opponent to move, the
+/// opponent can always take the tile that the lines through it make worth the most and `player` never fills a
+/// line. The worths are kept times `2^win_length`, so that they're whole numbers.
+struct Worth {
+    player: Player,
+    /// The empty tiles of the lines, one line after the other.
+    empty: Vec<usize>,
+    /// Where the tiles of every line end in `empty`, and what the line is worth.
+    lines: Vec<(usize, u64)>,
+    total: u64,
+    /// What the lines through every tile are worth.
+    tiles: Vec<u64>,
+    /// A worth of one.
+    one: u64,
+}
+
+impl Worth {
+    fn of(board: &Board, player: Player) -> Self {
+        let mut worth = Self {
+            player,
+            empty: Vec::new(),
+            lines: Vec::new(),
+            total: 0,
+            tiles: vec![0; board.width() * board.height()],
+            one: 1 << board.win_length(),
+        };
+
+        for_each_window(board, |owner, empty, stones| {
+            if owner != player {
+                return;
+            }
+
+            let line = 1 << stones;
+            for &tile in empty {
+                worth.empty.push(tile);
+                worth.tiles[tile] += line;
+            }
+            worth.lines.push((worth.empty.len(), line));
+            worth.total += line;
+        });
+        worth
+    }
+
+    fn lines(&self) -> impl Iterator<Item = (&[usize], u64)> + '_ {
+        self.lines.iter().scan(0, move |start, &(end, worth)| {
+            let line = &self.empty[*start..end];
+            *start = end;
+            Some((line, worth))
+        })
+    }
+
+    /// What the lines are worth after `player` places a stone at `tile`, which doubles the lines through it, or
+    /// after the opponent does, which takes those lines away.
+    fn after(&self, board: &Board, tile: usize) -> u64 {
+        if board.current_player == self.player {
+            self.total + self.tiles[tile]
+        } else {
+            self.total - self.tiles[tile]
+        }
+    }
+
+    /// Returns false if the opponent can stop every line. Before the opponent plays like the theorem says,
+    /// `player` gets to place one stone, or the opponent gets to take the tile worth the most. The
+    /// opponent may have to play forbidden moves for that, so then `player` can win as long as they have a line
+    /// left.
+    fn can_win(&self, board: &Board) -> bool {
+        if self.lines.is_empty() {
+            return false;
+        }
+        if board.rule().has_forbidden_moves(self.player.rotate()) {
+            return true;
+        }
+
+        let most = |tiles: &[u64]| tiles.iter().copied().max().unwrap();
+        if board.current_player == self.player {
+            return self.total + most(&self.tiles) >= self.one;
+        }
+        if self.total < self.one {
+            return false;
+        }
+
+        let taken = (0..self.tiles.len()).max_by_key(|&tile| self.tiles[tile]).unwrap();
+        let mut tiles = self.tiles.clone();
+        for (line, worth) in self.lines().filter(|(line, _)| line.contains(&taken)) {
+            for &tile in line {
+                tiles[tile] -= worth;
+            }
+        }
+        self.after(board, taken) + most(&tiles) >= self.one
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::ivec2;
+
+    #[test]
+    fn small_boards() {
+        assert!(matches!(ProofSolver::default().solve(&mut Board::new(3, 3, 3)), Solution::Draw(Some(_))));
+        assert!(matches!(ProofSolver::default().solve(&mut Board::new(4, 4, 3)), Solution::Win(_)));
+    }
+
+    #[test]
+    fn positions() {
+        let solve = |notation: &str| ProofSolver::default().solve(&mut Board::from_notation(notation).unwrap());
+        let at = |x, y, player| Move { pos: ivec2(x, y), player };
+        assert_eq!(solve("xx1/oo1/3 x freestyle 3"), Solution::Win(at(2, 0, Player::A)));
+        assert!(matches!(solve("4/1xx1/4/o3 o freestyle 3"), Solution::Loss(Some(_))));
+        assert_eq!(solve("xox/xoo/ox1 x freestyle 3"), Solution::Draw(Some(at(2, 2, Player::A))));
+        assert_eq!(solve("xox/xoo/oxx o freestyle 3"), Solution::Draw(None));
+        assert_eq!(solve("xxx/oo1/3 o freestyle 3"), Solution::Loss(None));
+    }
+
+    #[test]
+    fn four_in_a_row() {
+        assert!(matches!(ProofSolver::default().solve(&mut Board::new(4, 4, 4)), Solution::Draw(Some(_))));
+        assert!(matches!(ProofSolver::default().solve(&mut Board::new(5, 5, 4)), Solution::Draw(Some(_))));
+    }
+
+    #[test]
+    fn node_limit() {
+        assert_eq!(ProofSolver::new(10, 1 << 10).solve(&mut Board::new(5, 5, 4)), Solution::Unknown);
+    }
+
+    #[test]
+    fn table_limit() {
+        // A table too small for the search has to throw positions away, but the result is the same.
+        let solver = ProofSolver { table_limit: 1 << 10, ..ProofSolver::default() };
+        assert!(matches!(solver.solve(&mut Board::new(4, 4, 3)), Solution::Win(_)));
+    }
+
+    #[test]
+    fn worth() {
+        // Four rows, four columns and two diagonals, none of them with a stone yet.
+        let board = Board::new(4, 4, 4);
+        let worth = Worth::of(&board, Player::A);
+        assert_eq!((worth.total, worth.one), (10, 16));
+        assert_eq!((worth.tiles[0], worth.tiles[1]), (3, 2));
+        assert!(!worth.can_win(&board));
+
+        // The lines of `o` are the two outer rows and columns and a diagonal.
+        let board = Board::from_notation("4/1x2/2x1/4 o freestyle 4").unwrap();
+        let worth = Worth::of(&board, Player::B);
+        assert_eq!(worth.total, 5);
+        assert!(!worth.can_win(&board));
+        // `x` has a line with two stones and more with one, which is too much to be sure of stopping.
+        let worth = Worth::of(&board, Player::A);
+        assert_eq!((worth.total, worth.tiles[0]), (17, 6));
+        assert!(worth.can_win(&board));
+    }
+}
@@ -0,0 +1,226 @@
+//! Reading and writing games in the piskvork `.psq` format.
+//!
+//! A psq file starts with a header like `Piskvorky 15x15, 8:8, 0`, followed by one move per line as `x,y,time`,
+//! where the coordinates start at 1 and the time is in milliseconds. The moves end with a line containing `-1`,
+//! which can be followed by the names of the players.
+
+use crate::bitboard::MAX_LINE_LENGTH;
+use crate::board::{Board, Move, Player};
+use crate::record::GameRecord;
+use crate::rules::Rule;
+use glam::{IVec2, ivec2};
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PsqError {
+    MissingHeader,
+    InvalidHeader(String),
+    /// A move line that couldn't be read, with the line number starting at 1.
+    InvalidMove { line: usize, text: String },
+    /// A move that can't be done on the board, counting the moves from 0.
+    IllegalMove { index: usize, pos: IVec2 },
+}
+
+impl fmt::Display for PsqError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingHeader => write!(fmt, "the psq file is empty"),
+            Self::InvalidHeader(header) => write!(fmt, "invalid psq header {:?}", header),
+            Self::InvalidMove { line, text } => write!(fmt, "invalid move {:?} on line {}", text, line),
+            Self::IllegalMove { index, pos } => write!(fmt, "move {} at {},{} is not valid", index + 1, pos.x + 1, pos.y + 1),
+        }
+    }
+}
+
+impl Error for PsqError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PsqMove {
+    pub pos: IVec2,
+    /// How long the move took, if the file said.
+    pub time: Option<Duration>,
+}
+
+/// The contents of a psq file. The moves alternate between the colors, starting with `Player::A`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PsqGame {
+    pub width: usize,
+    pub height: usize,
+    pub moves: Vec<PsqMove>,
+    /// The names after the moves, usually the file names of the engines.
+    pub player_names: Vec<String>,
+}
+
+impl PsqGame {
+    pub fn parse(text: &str) -> Result<Self, PsqError> {
+        let mut lines = text.lines().enumerate().map(|(index, line)| (index + 1, line.trim()));
+
+        let header = lines.next().ok_or(PsqError::MissingHeader)?.1;
+        let (width, height) = parse_header(header).ok_or_else(|| PsqError::InvalidHeader(header.to_string()))?;
+
+        let mut moves = Vec::new();
+        for (line, text) in &mut lines {
+            if text.is_empty() {
+                continue;
+            }
+
+            if text == "-1" {
+                break;
+            }
+
+            let invalid = || PsqError::InvalidMove { line, text: text.to_string() };
+            let mut parts = text.split(',').map(|part| part.trim().parse::<i64>());
+            let x = parts.next().and_then(Result::ok).ok_or_else(invalid)?;
+            let y = parts.next().and_then(Result::ok).ok_or_else(invalid)?;
+            let time = match parts.next() {
+                Some(Ok(time)) if time >= 0 => Some(Duration::from_millis(time as u64)),
+                Some(_) => return Err(invalid()),
+                None => None,
+            };
+
+            if x < 1 || y < 1 || x > width as i64 || y > height as i64 {
+                return Err(invalid());
+            }
+
+            moves.push(PsqMove {
+                pos: ivec2(x as i32 - 1, y as i32 - 1),
+                time,
+            });
+        }
+
+        let player_names = lines
+            .map(|(_, text)| text)
+            .take_while(|&text| text != "-1")
+            .filter(|text| !text.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        Ok(Self {
+            width,
+            height,
+            moves,
+            player_names,
+        })
+    }
+
+    /// The moves of a match, including the stones that were on the starting board because of earlier moves. The
+    /// names are in color order.
+    pub fn from_record(record: &GameRecord) -> Self {
+        let start = record.start.history().iter().map(|r#move| PsqMove { pos: r#move.pos, time: None });
+        let played = record.moves.iter().map(|recorded| PsqMove {
+            pos: recorded.r#move.pos,
+            time: Some(recorded.time),
+        });
+
+        Self {
+            width: record.start.width(),
+            height: record.start.height(),
+            moves: start.chain(played).collect(),
+            player_names: vec![
+                record.name_of_color(Player::A).to_string(),
+                record.name_of_color(Player::B).to_string(),
+            ],
+        }
+    }
+
+    /// The moves with the colors filled in.
+    pub fn player_moves(&self) -> impl Iterator<Item = Move> + '_ {
+        self.moves.iter().enumerate().map(|(index, psq_move)| Move {
+            pos: psq_move.pos,
+            player: if index % 2 == 0 { Player::A } else { Player::B },
+        })
+    }
+
+    /// Plays the moves on a new board, which can be used for analysis or as the start of a match. Since the
+    /// format doesn't say which rules the game used, they have to be given.
+    pub fn to_board(&self, win_length: i32, rule: Rule) -> Result<Board, PsqError> {
+        let mut board = Board::new(self.width, self.height, win_length).with_rule(rule);
+        for (index, r#move) in self.player_moves().enumerate() {
+            if board.won.is_some() || !board.is_move_valid(r#move) {
+                return Err(PsqError::IllegalMove { index, pos: r#move.pos });
+            }
+            board.do_move(r#move);
+        }
+        Ok(board)
+    }
+}
+
+impl fmt::Display for PsqGame {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The second part is where the cursor was, the last move is as good as anything.
+        let cursor = self.moves.last().map_or(ivec2(self.width as i32 / 2, self.height as i32 / 2), |r#move| r#move.pos);
+        writeln!(fmt, "Piskvorky {}x{}, {}:{}, 0", self.width, self.height, cursor.x + 1, cursor.y + 1)?;
+
+        for r#move in &self.moves {
+            let time = r#move.time.map_or(0, |time| time.as_millis());
+            writeln!(fmt, "{},{},{}", r#move.pos.x + 1, r#move.pos.y + 1, time)?;
+        }
+
+        writeln!(fmt, "-1")?;
+        for name in &self.player_names {
+            writeln!(fmt, "{}", name)?;
+        }
+        if !self.player_names.is_empty() {
+            writeln!(fmt, "-1")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads the size from a header like `Piskvorky 15x15, 8:8, 0`. Sizes that don't fit on a `Board` are rejected.
+fn parse_header(header: &str) -> Option<(usize, usize)> {
+    let rest = header.strip_prefix("Piskvorky")?.trim_start();
+    let size = rest.split(',').next()?.trim();
+    let (width, height) = size.split_once('x')?;
+    let width = width.trim().parse().ok()?;
+    let height = height.trim().parse().ok()?;
+    if width == 0 || height == 0 || width > MAX_LINE_LENGTH || height > MAX_LINE_LENGTH {
+        return None;
+    }
+    Some((width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GAME: &str = "Piskvorky 15x15, 8:9, 0\n\
+        8,8,120\n\
+        9,8,80\n\
+        8,9,0\n\
+        -1\n\
+        pbrain-a.exe\n\
+        pbrain-b.exe\n\
+        -1\n";
+
+    #[test]
+    fn round_trip() {
+        let game = PsqGame::parse(GAME).unwrap();
+        assert_eq!((game.width, game.height), (15, 15));
+        assert_eq!(game.moves[0], PsqMove { pos: ivec2(7, 7), time: Some(Duration::from_millis(120)) });
+        assert_eq!(game.player_names, ["pbrain-a.exe", "pbrain-b.exe"]);
+
+        let board = game.to_board(5, Rule::Freestyle).unwrap();
+        assert_eq!(board.moves, 3);
+        assert_eq!(board.get(ivec2(7, 7)), Some(Some(Player::A)));
+        assert_eq!(board.get(ivec2(8, 7)), Some(Some(Player::B)));
+        assert_eq!(board.get(ivec2(7, 8)), Some(Some(Player::A)));
+        assert_eq!(board.current_player, Player::B);
+
+        assert_eq!(game.to_string(), GAME);
+        assert_eq!(PsqGame::parse(&game.to_string()).unwrap(), game);
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        assert_eq!(PsqGame::parse(""), Err(PsqError::MissingHeader));
+        assert!(matches!(PsqGame::parse("Piskvorky 100x100, 1:1, 0\n-1\n"), Err(PsqError::InvalidHeader(_))));
+        assert!(matches!(PsqGame::parse("Piskvorky 15x15, 1:1, 0\n16,1,0\n-1\n"), Err(PsqError::InvalidMove { line: 2, .. })));
+
+        let game = PsqGame::parse("Piskvorky 15x15, 1:1, 0\n1,1,0\n1,1,0\n-1\n").unwrap();
+        assert!(matches!(game.to_board(5, Rule::Freestyle), Err(PsqError::IllegalMove { index: 1, .. })));
+    }
+}
@@ -1,17 +1,99 @@
-use crate::{Ai, WORLD_SIZE};
+use crate::Ai;
 use crate::board::{Board, Move};
-use glam::ivec2;
+use crate::zobrist::splitmix64;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-pub struct Random;
+/// A small seedable random number generator, the splitmix64 sequence. The state is atomic so that it can be used
+/// through a shared reference, and from several threads.
+#[derive(Debug)]
+pub struct Rng {
+    state: AtomicU64,
+}
+
+impl Rng {
+    const INCREMENT: u64 = 0x9e37_79b9_7f4a_7c15;
+
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: AtomicU64::new(seed),
+        }
+    }
+
+    /// Seeds the generator from the clock, so that every run is different.
+    pub fn from_time() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_nanos() as u64);
+        Self::new(nanos)
+    }
+
+    pub fn seed(&self, seed: u64) {
+        self.state.store(seed, Ordering::Relaxed);
+    }
+
+    pub fn next_u64(&self) -> u64 {
+        // `splitmix64` adds the increment itself, so the old state is what gets mixed.
+        splitmix64(self.state.fetch_add(Self::INCREMENT, Ordering::Relaxed))
+    }
+
+    /// A uniformly distributed number in `0..limit`. `limit` has to be positive.
+    pub fn below(&self, limit: u64) -> u64 {
+        assert!(limit > 0, "Cannot pick a number below zero");
+
+        // Numbers in the last incomplete multiple of `limit` would make the low numbers more likely, so they're
+        // thrown away.
+        let zone = u64::MAX - u64::MAX % limit;
+        loop {
+            let value = self.next_u64();
+            if value < zone {
+                return value % limit;
+            }
+        }
+    }
+}
+
+impl Clone for Rng {
+    fn clone(&self) -> Self {
+        Self::new(self.state.load(Ordering::Relaxed))
+    }
+}
+
+/// Plays a uniformly random valid move.
+#[derive(Debug, Clone)]
+pub struct Random {
+    pub rng: Rng,
+}
+
+impl Default for Random {
+    fn default() -> Self {
+        Self {
+            rng: Rng::from_time(),
+        }
+    }
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: Rng::new(seed),
+        }
+    }
+}
 
 impl Ai for Random {
     fn name(&self) -> &str { "Random" }
 
+    fn seed(&self, seed: u64) {
+        self.rng.seed(seed);
+    }
+
     fn pick_move(&self, board: &mut Board) -> Option<Move> {
-        let hashset: std::collections::HashSet<_> = board.get_moves().collect();
-        hashset.into_iter().next().or(Some(Move {
-            pos: ivec2(WORLD_SIZE as i32 / 2, WORLD_SIZE as i32 / 2),
-            player: board.current_player,
-        }))
+        let moves: Vec<_> = board.get_moves().collect();
+        if moves.is_empty() {
+            return None;
+        }
+
+        Some(moves[self.rng.below(moves.len() as u64) as usize])
     }
 }
@@ -0,0 +1,51 @@
+use crate::GameResult;
+use crate::board::{Board, Move, Player};
+use crate::opening::Opening;
+use crate::rules::Rule;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordedMove {
+    pub r#move: Move,
+    /// How long the player took to decide on the move.
+    pub time: Duration,
+}
+
+/// Everything that happened in a match, enough to replay it.
+#[derive(Clone)]
+pub struct GameRecord {
+    /// The names of `player_a` and `player_b` of the match.
+    pub player_names: [String; 2],
+    /// The color `player_a` played as, which depends on the opening.
+    pub player_a_color: Player,
+    pub opening: Opening,
+    /// The board the match started on.
+    pub start: Board,
+    /// Every move of the match, including the opening stones.
+    pub moves: Vec<RecordedMove>,
+    pub result: GameResult,
+}
+
+impl GameRecord {
+    pub fn rule(&self) -> Rule {
+        self.start.rule()
+    }
+
+    /// The name of the player that played as `color`.
+    pub fn name_of_color(&self, color: Player) -> &str {
+        if color == self.player_a_color {
+            &self.player_names[0]
+        } else {
+            &self.player_names[1]
+        }
+    }
+
+    /// Plays all the moves of the match on the starting board, and returns the board the match ended on.
+    pub fn replay(&self) -> Board {
+        let mut board = self.start.clone();
+        for recorded in &self.moves {
+            board.do_move(recorded.r#move);
+        }
+        board
+    }
+}
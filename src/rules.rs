@@ -0,0 +1,325 @@
+//! The rule sets the game can be played with, and the line patterns needed to enforce them.
+
+use crate::board::{Player, Tile};
+use std::fmt;
+use std::str::FromStr;
+
+/// Which lines win the game, and which moves are forbidden.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rule {
+    /// Any line of at least the win length wins, and every move is allowed.
+    #[default]
+    Freestyle,
+    /// The first player (`Player::A`, black) only wins with a line of exactly the win length, and may not make
+    /// a double three, a double four or a line longer than the win length. The second player wins with any line
+    /// that is long enough.
+    Renju,
+    /// Only a line of exactly the win length wins, for both players.
+    Standard,
+    /// Any line of at least the win length wins, unless the opponent has stones at both of its ends. The edge of
+    /// the board doesn't block a line.
+    Caro,
+}
+
+impl Rule {
+    /// Returns true if a line longer than the win length doesn't win for `player`.
+    fn needs_exact_length(self, player: Player) -> bool {
+        match self {
+            Self::Standard => true,
+            Self::Renju => player == Player::A,
+            Self::Freestyle | Self::Caro => false,
+        }
+    }
+
+    /// Returns true if an unbroken line of `length` stones of `player`, with `ends` being the tiles just outside
+    /// of it, wins the game.
+    pub fn is_winning_line(self, player: Player, length: i32, ends: [Tile; 2], win_length: i32) -> bool {
+        if self.needs_exact_length(player) {
+            length == win_length
+        } else if self == Self::Caro {
+            length >= win_length && ends != [Some(player.rotate()); 2]
+        } else {
+            length >= win_length
+        }
+    }
+
+    /// Returns true if `player` filling a window of the win length, with `before` and `after` being the tiles
+    /// just outside of it, would win. Windows that can't win shouldn't count towards the score of a board.
+    pub fn can_win_window(self, player: Player, before: Tile, after: Tile) -> bool {
+        if self.needs_exact_length(player) {
+            before != Some(player) && after != Some(player)
+        } else if self == Self::Caro {
+            [before, after] != [Some(player.rotate()); 2]
+        } else {
+            true
+        }
+    }
+
+    /// Returns true if some moves are forbidden for `player`.
+    pub fn has_forbidden_moves(self, player: Player) -> bool {
+        self == Self::Renju && player == Player::A
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Freestyle => "freestyle",
+            Self::Renju => "renju",
+            Self::Standard => "standard",
+            Self::Caro => "caro",
+        };
+        write!(fmt, "{}", name)
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    /// Reads the name of a rule as written by `Display`, ignoring case.
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        match string.to_ascii_lowercase().as_str() {
+            "freestyle" => Ok(Self::Freestyle),
+            "renju" => Ok(Self::Renju),
+            "standard" => Ok(Self::Standard),
+            "caro" => Ok(Self::Caro),
+            _ => Err(format!("unknown rule {:?}", string)),
+        }
+    }
+}
+
+/// A cell of a line, seen from one of the players.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineCell {
+    Own,
+    Empty,
+    /// An opponent stone, or outside the board.
+    Blocked,
+}
+
+/// The length of the unbroken line of own stones through `index`.
+fn run_length(line: &[LineCell], index: usize) -> i32 {
+    let after = line[index..].iter().take_while(|&&cell| cell == LineCell::Own).count();
+    let before = line[..index].iter().rev().take_while(|&&cell| cell == LineCell::Own).count();
+    (before + after) as i32
+}
+
+/// The empty cells that would complete a line of exactly `win_length` stones going through `center`.
+fn five_completions(line: &mut [LineCell], center: usize, win_length: i32) -> Vec<usize> {
+    let reach = win_length as usize - 1;
+    let start = center.saturating_sub(reach);
+    let end = (center + reach).min(line.len() - 1);
+
+    (start..=end)
+        .filter(|&index| {
+            if line[index] != LineCell::Empty {
+                return false;
+            }
+
+            line[index] = LineCell::Own;
+            let completes = run_length(line, index) == win_length
+                && run_length(line, center) == win_length;
+            line[index] = LineCell::Empty;
+            completes
+        })
+        .collect()
+}
+
+/// The number of fours going through `center`. A four is a line that can become a five with one more stone. Two
+/// completions with all the same stones between them is one straight four, otherwise every completion is a
+/// separate four, like in `X.XXX.X`.
+pub fn four_count(line: &mut [LineCell], center: usize, win_length: i32) -> usize {
+    let completions = five_completions(line, center, win_length);
+    match completions.as_slice() {
+        [a, b] if b - a == win_length as usize => 1,
+        _ => completions.len(),
+    }
+}
+
+/// Returns true if there's a straight four through `center`, a four that can be completed at both ends.
+fn is_straight_four(line: &mut [LineCell], center: usize, win_length: i32) -> bool {
+    matches!(
+        five_completions(line, center, win_length).as_slice(),
+        [a, b] if b - a == win_length as usize
+    )
+}
+
+/// Returns true if there's an open three through `center`, a three that can become a straight four with one more
+/// stone.
+///
+/// Strictly the stone making the straight four must not be forbidden itself, that isn't checked here.
+pub fn is_open_three(line: &mut [LineCell], center: usize, win_length: i32) -> bool {
+    let reach = win_length as usize - 1;
+    let start = center.saturating_sub(reach);
+    let end = (center + reach).min(line.len() - 1);
+
+    (start..=end).any(|index| {
+        if line[index] != LineCell::Empty {
+            return false;
+        }
+
+        line[index] = LineCell::Own;
+        let straight_four = run_length(line, index) < win_length && is_straight_four(line, center, win_length);
+        line[index] = LineCell::Empty;
+        straight_four
+    })
+}
+
+/// Returns true if placing a stone in the middle of these lines is forbidden under the renju rules. Every line
+/// has the new stone in the middle, already placed.
+pub fn is_renju_forbidden(lines: &mut [Vec<LineCell>], win_length: i32) -> bool {
+    let center = lines[0].len() / 2;
+
+    // A five wins even if it would be forbidden otherwise.
+    if lines.iter().any(|line| run_length(line, center) == win_length) {
+        return false;
+    }
+
+    if lines.iter().any(|line| run_length(line, center) > win_length) {
+        return true;
+    }
+
+    let mut fours = 0;
+    let mut threes = 0;
+    for line in lines.iter_mut() {
+        let line_fours = four_count(line, center, win_length);
+        fours += line_fours;
+        if line_fours == 0 && is_open_three(line, center, win_length) {
+            threes += 1;
+        }
+    }
+
+    fours >= 2 || threes >= 2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{Board, Move, MoveError};
+    use glam::{ivec2, IVec2};
+
+    /// Every test places its stone in the middle of the board.
+    fn center() -> IVec2 {
+        ivec2(4, 4)
+    }
+    const EMPTY: &str = ".........";
+
+    /// Reads a board drawn with `.` for empty tiles, one string per row.
+    fn board(rows: &[&str], player: &str, rule: Rule) -> Board {
+        let rows: Vec<String> = rows
+            .iter()
+            .map(|row| {
+                let mut notation = String::new();
+                let mut empty = 0;
+                for c in row.chars() {
+                    if c == '.' {
+                        empty += 1;
+                        continue;
+                    }
+                    if empty > 0 {
+                        notation += &std::mem::take(&mut empty).to_string();
+                    }
+                    notation.push(c);
+                }
+                if empty > 0 {
+                    notation += &empty.to_string();
+                }
+                notation
+            })
+            .collect();
+        Board::from_notation(&format!("{} {} {} 5", rows.join("/"), player, rule)).unwrap()
+    }
+
+    fn forbidden(rows: &[&str]) -> bool {
+        board(rows, "x", Rule::Renju).is_forbidden(center(), Player::A)
+    }
+
+    const DOUBLE_THREE: [&str; 9] = [
+        ".........",
+        ".........",
+        "....x....",
+        "....x....",
+        "..xx.....",
+        ".........",
+        ".........",
+        ".........",
+        ".........",
+    ];
+
+    #[test]
+    fn double_threes() {
+        assert!(forbidden(&DOUBLE_THREE));
+
+        // With the row blocked on one side, it can't become a straight four anymore.
+        let mut blocked = DOUBLE_THREE;
+        blocked[4] = ".oxx.....";
+        assert!(!forbidden(&blocked));
+
+        // Only black has forbidden moves, and only in renju.
+        assert!(!board(&DOUBLE_THREE, "o", Rule::Renju).is_forbidden(center(), Player::B));
+        for rule in [Rule::Freestyle, Rule::Standard, Rule::Caro] {
+            assert!(!board(&DOUBLE_THREE, "x", rule).is_forbidden(center(), Player::A), "{}", rule);
+        }
+    }
+
+    #[test]
+    fn double_fours() {
+        let rows = [
+            "....o....",
+            "....x....",
+            "....x....",
+            "....x....",
+            "oxxx.....",
+            ".........",
+            ".........",
+            ".........",
+            ".........",
+        ];
+        assert!(forbidden(&rows));
+
+        // Both fours are on the same line, as in X.XXX.X.
+        let mut rows = [EMPTY; 9];
+        rows[4] = ".x.x.x.x.";
+        assert!(forbidden(&rows));
+    }
+
+    #[test]
+    fn overlines() {
+        let mut rows = [EMPTY; 9];
+        rows[4] = "xxxx.x...";
+        assert!(forbidden(&rows));
+        assert!(!board(&rows, "x", Rule::Freestyle).is_forbidden(center(), Player::A));
+    }
+
+    #[test]
+    fn fives_are_never_forbidden() {
+        // The stone makes a five along the row and a double three along the column and the diagonal.
+        let rows = [
+            ".........",
+            ".........",
+            "..x.x....",
+            "...xx....",
+            "xxxx.....",
+            ".........",
+            ".........",
+            ".........",
+            ".........",
+        ];
+        assert!(!forbidden(&rows));
+        assert!(board(&rows, "x", Rule::Renju).wins_at(center(), Player::A));
+    }
+
+    #[test]
+    fn forbidden_moves_are_not_offered() {
+        let black = board(&DOUBLE_THREE, "x", Rule::Renju);
+        assert!(black.get_moves().all(|r#move| r#move.pos != center()));
+        assert_eq!(
+            black.check_move(Move { pos: center(), player: Player::A }),
+            Err(MoveError::Forbidden(center()))
+        );
+
+        let white = board(&DOUBLE_THREE, "o", Rule::Renju);
+        assert!(white.get_moves().any(|r#move| r#move.pos == center()));
+        assert_eq!(white.check_move(Move { pos: center(), player: Player::B }), Ok(()));
+    }
+}
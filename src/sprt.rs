@@ -0,0 +1,231 @@
+//! Sequential probability ratio tests, to find out if a change to an `Ai` makes it stronger with as few games as
+//! possible.
+//!
+//! The games are scored with the generalized SPRT, which approximates the log likelihood ratio of the two
+//! hypotheses from the mean and variance of the game scores, so that draws are accounted for.
+
+use crate::{GameResult, MatchOptions, run_match};
+use crate::board::{Board, Move, Player};
+use crate::random::Rng;
+use crate::tournament::{Entrant, Tally};
+use rayon::prelude::*;
+
+/// How far from the center the stones of the random openings are placed.
+const OPENING_RADIUS: i32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SprtResult {
+    /// The candidate is at least `elo1` stronger.
+    Accepted,
+    /// The candidate is at most `elo0` stronger.
+    Rejected,
+    /// The games ran out before the test could decide.
+    Inconclusive,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SprtOutcome {
+    pub result: SprtResult,
+    /// The results of the candidate against the baseline.
+    pub tally: Tally,
+    pub llr: f64,
+}
+
+/// A test of the hypothesis that the candidate is `elo1` Elo stronger than the baseline, against the hypothesis
+/// that it's only `elo0` stronger.
+#[derive(Clone)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    /// The chance of accepting a change that isn't an improvement.
+    pub alpha: f64,
+    /// The chance of rejecting a change that is an improvement.
+    pub beta: f64,
+    /// The test gives up after this many games.
+    pub max_games: usize,
+    /// How many random stones every opening has.
+    pub opening_stones: usize,
+    /// The options every game is played with. The board is where the random openings are placed, and the seed
+    /// decides the openings.
+    pub options: MatchOptions,
+}
+
+impl Sprt {
+    pub fn new(elo0: f64, elo1: f64) -> Self {
+        Self {
+            elo0,
+            elo1,
+            alpha: 0.05,
+            beta: 0.05,
+            max_games: 10_000,
+            opening_stones: 2,
+            options: MatchOptions::default(),
+        }
+    }
+
+    pub fn with_error_rates(mut self, alpha: f64, beta: f64) -> Self {
+        self.alpha = alpha;
+        self.beta = beta;
+        self
+    }
+
+    pub fn with_max_games(mut self, max_games: usize) -> Self {
+        self.max_games = max_games;
+        self
+    }
+
+    pub fn with_opening_stones(mut self, opening_stones: usize) -> Self {
+        self.opening_stones = opening_stones;
+        self
+    }
+
+    pub fn with_options(mut self, options: MatchOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// The log likelihood ratios at which the change is rejected and accepted.
+    pub fn bounds(&self) -> (f64, f64) {
+        ((self.beta / (1.0 - self.alpha)).ln(), ((1.0 - self.beta) / self.alpha).ln())
+    }
+
+    /// The log likelihood ratio of the two hypotheses given the results of the candidate.
+    pub fn llr(&self, tally: Tally) -> f64 {
+        let games = tally.games() as f64;
+        if games == 0.0 {
+            return 0.0;
+        }
+
+        // Without a pseudo-draw, a candidate that wins or loses every game has no variance, and the ratio can't be
+        // computed at all. With it the ratio grows with every game as it should.
+        let variance = Tally { draws: tally.draws + 1, ..tally }.variance();
+        let score = tally.score();
+        let score0 = expected_score(self.elo0);
+        let score1 = expected_score(self.elo1);
+        games * (score1 - score0) * (2.0 * score - score0 - score1) / (2.0 * variance)
+    }
+
+    /// Plays games until the test decides, printing the progress after every pair of games. Every opening is
+    /// played twice, with the colors swapped.
+    pub fn run(&self, baseline: &Entrant, candidate: &Entrant) -> SprtOutcome {
+        let rng = Rng::new(self.options.seed.unwrap_or(0));
+        let (lower, upper) = self.bounds();
+        let batch_size = rayon::current_num_threads().max(1);
+
+        let mut tally = Tally::default();
+        let mut llr = 0.0;
+        while tally.games() < self.max_games as u32 {
+            let openings: Vec<(u64, Board)> = (0..batch_size)
+                .map(|_| (rng.next_u64(), random_opening(&self.options.board, self.opening_stones, &rng)))
+                .collect();
+
+            let results: Vec<[GameResult; 2]> = openings
+                .into_par_iter()
+                .map(|(seed, board)| {
+                    let options = MatchOptions { board, seed: Some(seed), ..self.options.clone() };
+                    [
+                        run_match(candidate.create(), baseline.create(), &options).result,
+                        // The result is from the point of view of `player_a`, so this one is flipped.
+                        flip(run_match(baseline.create(), candidate.create(), &options).result),
+                    ]
+                })
+                .collect();
+
+            for result in results.iter().flatten() {
+                match result.winner() {
+                    Some(Player::A) => tally.wins += 1,
+                    Some(Player::B) => tally.losses += 1,
+                    None => tally.draws += 1,
+                }
+            }
+
+            llr = self.llr(tally);
+            println!(
+                "{} games, {}/{}/{}, LLR {:.2} ({:.2}, {:.2})",
+                tally.games(),
+                tally.wins,
+                tally.draws,
+                tally.losses,
+                llr,
+                lower,
+                upper,
+            );
+
+            if llr >= upper {
+                return SprtOutcome { result: SprtResult::Accepted, tally, llr };
+            } else if llr <= lower {
+                return SprtOutcome { result: SprtResult::Rejected, tally, llr };
+            }
+        }
+
+        SprtOutcome { result: SprtResult::Inconclusive, tally, llr }
+    }
+}
+
+/// The points per game a player is expected to score against someone `elo` weaker.
+fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10_f64.powf(-elo / 400.0))
+}
+
+/// Swaps the players of a result.
+fn flip(result: GameResult) -> GameResult {
+    match result {
+        GameResult::Won(player) => GameResult::Won(player.rotate()),
+        GameResult::Draw => GameResult::Draw,
+        GameResult::Forfeit(player) => GameResult::Forfeit(player.rotate()),
+        GameResult::IllegalMove { culprit, r#move, error } => GameResult::IllegalMove { culprit: culprit.rotate(), r#move, error },
+    }
+}
+
+/// Places `stones` random stones close to the center of the board, alternating between the colors.
+fn random_opening(board: &Board, stones: usize, rng: &Rng) -> Board {
+    let mut board = board.clone();
+    let center = board.center();
+    for _ in 0..stones {
+        let moves: Vec<Move> = board
+            .get_moves()
+            .filter(|r#move| (r#move.pos - center).abs().max_element() <= OPENING_RADIUS)
+            .collect();
+        if moves.is_empty() || board.won.is_some() {
+            break;
+        }
+
+        board.do_move(moves[rng.below(moves.len() as u64) as usize]);
+    }
+    board
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounds() {
+        let (lower, upper) = Sprt::new(0.0, 10.0).bounds();
+        assert!((lower + 19_f64.ln()).abs() < 1e-9);
+        assert!((upper - 19_f64.ln()).abs() < 1e-9);
+
+        let (lower, upper) = Sprt::new(0.0, 10.0).with_error_rates(0.01, 0.1).bounds();
+        assert!((lower - (0.1_f64 / 0.99).ln()).abs() < 1e-9);
+        assert!((upper - 90_f64.ln()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn llr() {
+        let sprt = Sprt::new(0.0, 10.0);
+        let (lower, upper) = sprt.bounds();
+        assert_eq!(sprt.llr(Tally::default()), 0.0);
+
+        // An even result is evidence against an improvement, and a lopsided one for it.
+        assert!(sprt.llr(Tally { wins: 100, draws: 100, losses: 100 }) < 0.0);
+        assert!(sprt.llr(Tally { wins: 150, draws: 100, losses: 50 }) > upper);
+        assert!(sprt.llr(Tally { wins: 50, draws: 100, losses: 150 }) < lower);
+
+        // Winning every game has no variance, but still has to decide the test eventually.
+        let wins = |wins| sprt.llr(Tally { wins, draws: 0, losses: 0 });
+        assert!(wins(1) > 0.0 && wins(1) < upper);
+        assert!(wins(2) > wins(1));
+        assert!(wins(20) > upper);
+        assert!(sprt.llr(Tally { wins: 0, draws: 0, losses: 20 }) < lower);
+    }
+}
@@ -1,5 +1,7 @@
 use crate::Ai;
-use crate::board::{Board, Move};
+use crate::time_control::TimeControl;
+use crate::board::{Board, Move, Player};
+use crate::opening::Swap2Choice;
 
 #[derive(Clone, Copy)]
 pub struct Switch<A, B>(pub A, pub B, pub usize);
@@ -11,11 +13,46 @@ impl<A, B> Ai for Switch<A, B> where A: Ai, B: Ai {
 
     fn name(&self) -> &str { "Switch" }
 
+    fn set_time_control(&mut self, time_control: TimeControl) {
+        self.0.set_time_control(time_control);
+        self.1.set_time_control(time_control);
+    }
+
+    fn set_memory_limit(&mut self, bytes: u64) {
+        // Only one of them plays at a time, but both keep their memory.
+        self.0.set_memory_limit(bytes / 2);
+        self.1.set_memory_limit(bytes / 2);
+    }
+
+    fn seed(&self, seed: u64) {
+        self.0.seed(seed);
+        self.1.seed(seed);
+    }
+
     fn pick_move(&self, board: &mut Board) -> Option<Move> {
+        self.current(board).pick_move(board)
+    }
+
+    fn place_opening(&self, board: &mut Board, count: usize) -> Option<Vec<Move>> {
+        self.current(board).place_opening(board, count)
+    }
+
+    fn choose_color(&self, board: &mut Board) -> Option<Player> {
+        self.current(board).choose_color(board)
+    }
+
+    fn choose_swap2(&self, board: &mut Board) -> Option<Swap2Choice> {
+        self.current(board).choose_swap2(board)
+    }
+}
+
+impl<A, B> Switch<A, B> where A: Ai, B: Ai {
+    /// The `Ai` that is in charge at this point of the game.
+    fn current(&self, board: &Board) -> &dyn Ai {
         if board.moves >= self.2 * 2 {
-            self.1.pick_move(board)
+            &self.1
         } else {
-            self.0.pick_move(board)
+            &self.0
         }
     }
 }
@@ -0,0 +1,309 @@
+//! Threat-space search, finds wins that are forced by making threats the opponent has to answer.
+//!
+//! A victory by continuous fours (VCF) only uses fours, so every defending move is forced. A victory by continuous
+//! threats (VCT) also uses threes, which the defender can answer in several ways, so it's much more expensive.
+
+use crate::board::{Board, Move, Player, DIRECTIONS};
+use glam::IVec2;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ThreatSolver {
+    /// The maximum number of fours in a VCF.
+    pub vcf_depth: u32,
+    /// The maximum number of threes in a VCT, every three can be followed by a VCF.
+    pub vct_depth: u32,
+    /// The number of attacking moves to try before giving up on a single solve.
+    pub node_limit: u64,
+}
+
+impl Default for ThreatSolver {
+    fn default() -> Self {
+        Self {
+            vcf_depth: 16,
+            vct_depth: 3,
+            node_limit: 50_000,
+        }
+    }
+}
+
+impl ThreatSolver {
+    pub fn new(vcf_depth: u32, vct_depth: u32, node_limit: u64) -> Self {
+        Self {
+            vcf_depth,
+            vct_depth,
+            node_limit,
+        }
+    }
+
+    /// Tries to find a victory by continuous fours for the current player. The returned moves alternate between the
+    /// attacker and the defender, and end with the winning move.
+    pub fn solve_vcf(&self, board: &mut Board) -> Option<Vec<Move>> {
+        self.vcf(board, self.vcf_depth, &mut 0)
+    }
+
+    /// Tries to find a victory by continuous threats for the current player. The defender may have several answers
+    /// to a three, the returned moves are the line where the defender tries the first of them.
+    pub fn solve_vct(&self, board: &mut Board) -> Option<Vec<Move>> {
+        self.vct(board, self.vct_depth, &mut 0)
+    }
+
+    fn out_of_nodes(&self, nodes: &mut u64) -> bool {
+        *nodes += 1;
+        *nodes > self.node_limit
+    }
+
+    fn vcf(&self, board: &mut Board, depth: u32, nodes: &mut u64) -> Option<Vec<Move>> {
+        let attacker = board.current_player;
+        let defender = attacker.rotate();
+
+        if board.won.is_some() {
+            return None;
+        }
+
+        if let Some(&pos) = board.winning_cells(attacker).first() {
+            return Some(vec![Move { pos, player: attacker }]);
+        }
+
+        // A four doesn't stop the defender from completing a five of their own.
+        if depth == 0 || !board.winning_cells(defender).is_empty() {
+            return None;
+        }
+
+        for pos in line_cells(board, attacker, board.win_length() - 2) {
+            if self.out_of_nodes(nodes) {
+                return None;
+            }
+
+            let r#move = Move { pos, player: attacker };
+            if !board.is_move_valid(r#move) {
+                continue;
+            }
+
+            let handle = board.do_reversible_move(r#move);
+            let threats = handle.board.winning_cells(attacker);
+            let block = match threats.first() {
+                Some(&pos) => Move { pos, player: defender },
+                None => continue,
+            };
+
+            // If there are several threats the defender can only block one of them, and the search finds the
+            // winning move right away.
+            let block_handle = handle.board.do_reversible_move(block);
+            if block_handle.board.won.is_some() {
+                continue;
+            }
+
+            if let Some(rest) = self.vcf(block_handle.board, depth - 1, nodes) {
+                let mut line = vec![r#move, block];
+                line.extend(rest);
+                return Some(line);
+            }
+        }
+
+        None
+    }
+
+    fn vct(&self, board: &mut Board, depth: u32, nodes: &mut u64) -> Option<Vec<Move>> {
+        let attacker = board.current_player;
+        let defender = attacker.rotate();
+
+        if board.won.is_some() {
+            return None;
+        }
+
+        if let Some(line) = self.vcf(board, self.vcf_depth, nodes) {
+            return Some(line);
+        }
+
+        if depth == 0 || !board.winning_cells(defender).is_empty() {
+            return None;
+        }
+
+        let win_length = board.win_length();
+        let mut candidates = line_cells(board, attacker, win_length - 2);
+        for pos in line_cells(board, attacker, win_length - 3) {
+            if !candidates.contains(&pos) {
+                candidates.push(pos);
+            }
+        }
+
+        'candidates: for pos in candidates {
+            if self.out_of_nodes(nodes) {
+                return None;
+            }
+
+            let r#move = Move { pos, player: attacker };
+            if !board.is_move_valid(r#move) {
+                continue;
+            }
+
+            let handle = board.do_reversible_move(r#move);
+
+            if let Some(&block) = handle.board.winning_cells(attacker).first() {
+                // A four, the defender only has one answer.
+                let block = Move { pos: block, player: defender };
+                let block_handle = handle.board.do_reversible_move(block);
+                if block_handle.board.won.is_some() {
+                    continue;
+                }
+
+                if let Some(rest) = self.vct(block_handle.board, depth - 1, nodes) {
+                    let mut line = vec![r#move, block];
+                    line.extend(rest);
+                    return Some(line);
+                }
+
+                continue;
+            }
+
+            // The defender ignores a three if they can win with fours of their own.
+            if self.vcf(handle.board, self.vcf_depth, nodes).is_some() {
+                continue;
+            }
+
+            // A three only threatens something if the attacker could win with fours if they got to move again.
+            handle.board.pass();
+            let threat = self.vcf(handle.board, self.vcf_depth, nodes);
+            handle.board.pass();
+            let threat = match threat {
+                Some(threat) => threat,
+                None => continue,
+            };
+
+            let mut main_line = None;
+            for defense in defenses(handle.board, &threat, attacker) {
+                let defense = Move { pos: defense, player: defender };
+                let defense_handle = handle.board.do_reversible_move(defense);
+                if defense_handle.board.won.is_some() {
+                    continue 'candidates;
+                }
+
+                match self.vct(defense_handle.board, depth - 1, nodes) {
+                    Some(rest) => {
+                        if main_line.is_none() {
+                            main_line = Some((defense, rest));
+                        }
+                    }
+                    None => continue 'candidates,
+                }
+            }
+
+            if let Some((defense, rest)) = main_line {
+                let mut line = vec![r#move, defense];
+                line.extend(rest);
+                return Some(line);
+            }
+        }
+
+        None
+    }
+}
+
+/// The empty cells in lines of `win_length` cells that have exactly `stones` stones of `player` and none of the
+/// opponent, so that playing there brings the line closer to a five. Cells that are in more of these lines come
+/// first.
+fn line_cells(board: &Board, player: Player, stones: i32) -> Vec<IVec2> {
+    if stones < 0 {
+        return Vec::new();
+    }
+
+    let win_length = board.win_length();
+    let mut counts = vec![0_u32; board.width() * board.height()];
+    let index = |pos: IVec2| pos.y as usize * board.width() + pos.x as usize;
+
+    for start in board.positions() {
+        for &direction in DIRECTIONS.iter() {
+            if board.get(start + direction * (win_length - 1)).is_none() {
+                continue;
+            }
+
+            let mut own = 0;
+            let mut blocked = false;
+            for i in 0..win_length {
+                match board.get(start + direction * i) {
+                    Some(Some(owner)) if owner == player => own += 1,
+                    Some(Some(_)) => blocked = true,
+                    _ => {}
+                }
+            }
+
+            if blocked || own != stones {
+                continue;
+            }
+
+            for i in 0..win_length {
+                let pos = start + direction * i;
+                if board.get(pos) == Some(None) {
+                    counts[index(pos)] += 1;
+                }
+            }
+        }
+    }
+
+    let mut cells: Vec<IVec2> = board.positions().filter(|&pos| counts[index(pos)] > 0).collect();
+    cells.sort_by_key(|&pos| std::cmp::Reverse(counts[index(pos)]));
+    cells
+}
+
+/// The moves the defender has to consider against a three. Those are the cells of every line the attacker's
+/// following fours could use, and the moves that make a four for the defender.
+fn defenses(board: &Board, threat: &[Move], attacker: Player) -> Vec<IVec2> {
+    let win_length = board.win_length();
+    let mut cells = line_cells(board, attacker.rotate(), win_length - 2);
+
+    for r#move in threat.iter().filter(|r#move| r#move.player == attacker) {
+        for &direction in DIRECTIONS.iter() {
+            for i in -(win_length - 1)..win_length {
+                let pos = r#move.pos + direction * i;
+                if board.get(pos) == Some(None) && !cells.contains(&pos) {
+                    cells.push(pos);
+                }
+            }
+        }
+    }
+
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Plays a line from the solver and returns the winner at the end of it.
+    fn play(notation: &str, line: &[Move]) -> Option<Player> {
+        let mut board = Board::from_notation(notation).unwrap();
+        for &r#move in line {
+            board.do_move(r#move);
+        }
+        board.won
+    }
+
+    #[test]
+    fn vcf() {
+        // Both the row and the column are blocked on one side, x at d6 makes two fours.
+        let notation = "9/9/9/2oxxx3/6x2/6x2/6x2/6o2/9 x freestyle 5";
+        let line = ThreatSolver::default().solve_vcf(&mut Board::from_notation(notation).unwrap()).unwrap();
+        assert_eq!(play(notation, &line), Some(Player::A));
+    }
+
+    #[test]
+    fn vct() {
+        // Two open twos that make a double three, there are no fours to play yet.
+        let notation = "9/9/5x3/5x3/3xx4/9/9/9/9 x freestyle 5";
+        let solver = ThreatSolver::default();
+        assert!(solver.solve_vcf(&mut Board::from_notation(notation).unwrap()).is_none());
+
+        let line = solver.solve_vct(&mut Board::from_notation(notation).unwrap()).unwrap();
+        assert_eq!(line[0], Move { pos: IVec2::new(5, 4), player: Player::A });
+        assert_eq!(play(notation, &line), Some(Player::A));
+    }
+
+    #[test]
+    fn counter_fours() {
+        // The same double three, but o wins with fours first.
+        let notation = "9/9/5x3/5x3/3xx4/7o1/7o1/7o1/4ooo2 x freestyle 5";
+        let mut board = Board::from_notation(notation).unwrap();
+        assert!(ThreatSolver::default().solve_vct(&mut board).is_none());
+        assert_eq!(board.to_notation(), notation);
+    }
+}
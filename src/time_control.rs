@@ -0,0 +1,26 @@
+use std::time::Duration;
+
+/// How much time an `Ai` is allowed to spend on its moves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeControl {
+    /// A fixed amount of time for every move.
+    PerMove(Duration),
+    /// The time left on the clock for the rest of the game, and the time added to it after every move.
+    Clock { remaining: Duration, increment: Duration },
+}
+
+impl TimeControl {
+    /// The number of moves we expect to still have to make when playing on a clock.
+    const EXPECTED_MOVES_LEFT: u32 = 30;
+
+    /// How long the next move may take.
+    pub fn move_budget(self) -> Duration {
+        match self {
+            Self::PerMove(duration) => duration,
+            Self::Clock { remaining, increment } => {
+                // Never plan on using more than half of the clock, so that a few long moves can't flag us.
+                (remaining / Self::EXPECTED_MOVES_LEFT + increment * 3 / 4).min(remaining / 2)
+            }
+        }
+    }
+}
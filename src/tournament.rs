@@ -0,0 +1,258 @@
+//! Round-robin tournaments between `Ai`s, to find out which of them plays best.
+
+use crate::{Ai, GameResult, MatchOptions, run_match};
+use crate::board::Player;
+use rayon::prelude::*;
+use std::fmt;
+
+/// A participant of a tournament. Every game gets a new `Ai` from the factory, so that nothing is carried over
+/// between games that are played at the same time.
+pub struct Entrant {
+    pub name: String,
+    factory: Box<dyn Fn() -> Box<dyn Ai> + Send + Sync>,
+}
+
+impl Entrant {
+    pub fn new<A>(name: impl Into<String>, factory: impl Fn() -> A + Send + Sync + 'static) -> Self
+    where
+        A: Ai + 'static,
+    {
+        Self {
+            name: name.into(),
+            factory: Box::new(move || Box::new(factory())),
+        }
+    }
+
+    /// A new `Ai` for a game.
+    pub fn create(&self) -> Box<dyn Ai> {
+        (self.factory)()
+    }
+}
+
+pub struct Tournament {
+    pub entrants: Vec<Entrant>,
+    /// How many games every pair of entrants plays. They take turns being `player_a`.
+    pub games_per_pair: usize,
+    /// The options every game is played with. If there is a seed, every game gets its own seed derived from it.
+    pub options: MatchOptions,
+}
+
+impl Tournament {
+    pub fn new(games_per_pair: usize, options: MatchOptions) -> Self {
+        Self {
+            entrants: Vec::new(),
+            games_per_pair,
+            options,
+        }
+    }
+
+    pub fn with_entrant<A>(mut self, name: impl Into<String>, factory: impl Fn() -> A + Send + Sync + 'static) -> Self
+    where
+        A: Ai + 'static,
+    {
+        self.entrants.push(Entrant::new(name, factory));
+        self
+    }
+
+    /// Plays every game of the tournament, as many at the same time as rayon allows.
+    pub fn run(&self) -> Standings {
+        let count = self.entrants.len();
+        let games: Vec<(usize, usize, usize)> = (0..count)
+            .flat_map(|first| (first + 1..count).map(move |second| (first, second)))
+            .flat_map(|(first, second)| (0..self.games_per_pair).map(move |game| (first, second, game)))
+            .collect();
+
+        let results: Vec<(usize, usize, GameResult)> = games
+            .into_par_iter()
+            .enumerate()
+            .map(|(index, (first, second, game))| {
+                // Swapping who is `player_a` swaps the colors, whatever the opening decides.
+                let (a, b) = if game % 2 == 0 { (first, second) } else { (second, first) };
+                let options = MatchOptions {
+                    seed: self.options.seed.map(|seed| crate::zobrist::splitmix64(seed ^ index as u64)),
+                    ..self.options.clone()
+                };
+                let record = run_match(self.entrants[a].create(), self.entrants[b].create(), &options);
+                (a, b, record.result)
+            })
+            .collect();
+
+        let mut standings = Standings {
+            names: self.entrants.iter().map(|entrant| entrant.name.clone()).collect(),
+            tallies: vec![vec![Tally::default(); count]; count],
+        };
+        for (a, b, result) in results {
+            standings.add(a, b, &result);
+        }
+        standings
+    }
+}
+
+/// Wins, draws and losses from the point of view of one player.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Tally {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl Tally {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// The average points per game, counting a draw as half a win.
+    pub fn score(&self) -> f64 {
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games() as f64
+    }
+
+    /// The variance of the points of a single game.
+    pub fn variance(&self) -> f64 {
+        let score = self.score();
+        (self.wins as f64 * (1.0 - score).powi(2)
+            + self.draws as f64 * (0.5 - score).powi(2)
+            + self.losses as f64 * score.powi(2))
+            / self.games() as f64
+    }
+
+    /// The Elo difference that the results suggest, and the margin of the 95% confidence interval around it.
+    /// Results without any losses or without any wins have an infinite difference.
+    pub fn elo(&self) -> (f64, f64) {
+        let score = self.score();
+        let margin = 1.96 * (self.variance() / self.games() as f64).sqrt();
+
+        if score <= 0.0 || score >= 1.0 {
+            return (elo_difference(score), f64::INFINITY);
+        }
+
+        let low = elo_difference((score - margin).max(0.0));
+        let high = elo_difference((score + margin).min(1.0));
+        (elo_difference(score), (high - low) / 2.0)
+    }
+}
+
+impl std::ops::AddAssign for Tally {
+    fn add_assign(&mut self, other: Self) {
+        self.wins += other.wins;
+        self.draws += other.draws;
+        self.losses += other.losses;
+    }
+}
+
+/// The Elo difference between two players where one scores `score` points per game against the other.
+pub fn elo_difference(score: f64) -> f64 {
+    // Adding zero turns a negative zero into a positive one, so that even results aren't written as -0.
+    -400.0 * (1.0 / score - 1.0).log10() + 0.0
+}
+
+/// The results of a tournament.
+#[derive(Debug, Clone)]
+pub struct Standings {
+    pub names: Vec<String>,
+    /// `tallies[i][j]` are the results of entrant `i` against entrant `j`.
+    pub tallies: Vec<Vec<Tally>>,
+}
+
+impl Standings {
+    fn add(&mut self, a: usize, b: usize, result: &GameResult) {
+        match result.winner() {
+            Some(Player::A) => {
+                self.tallies[a][b].wins += 1;
+                self.tallies[b][a].losses += 1;
+            }
+            Some(Player::B) => {
+                self.tallies[a][b].losses += 1;
+                self.tallies[b][a].wins += 1;
+            }
+            None => {
+                self.tallies[a][b].draws += 1;
+                self.tallies[b][a].draws += 1;
+            }
+        }
+    }
+
+    /// The results of an entrant against everyone else.
+    pub fn total(&self, entrant: usize) -> Tally {
+        let mut total = Tally::default();
+        for &tally in &self.tallies[entrant] {
+            total += tally;
+        }
+        total
+    }
+}
+
+impl fmt::Display for Standings {
+    /// A table of the wins, draws and losses of every pair with the Elo of every entrant against the rest of the
+    /// field, followed by the Elo differences of every pair.
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name_width = self.names.iter().map(|name| name.len()).max().unwrap_or(0);
+        let cell_width = self
+            .tallies
+            .iter()
+            .flatten()
+            .map(|tally| format!("{}/{}/{}", tally.wins, tally.draws, tally.losses).len())
+            .chain(self.names.iter().map(|name| name.len()))
+            .max()
+            .unwrap_or(0);
+
+        write!(fmt, "{:1$}", "", name_width)?;
+        for name in &self.names {
+            write!(fmt, " | {:>1$}", name, cell_width)?;
+        }
+        writeln!(fmt, " | {:>11} | Elo", "total")?;
+
+        for (i, name) in self.names.iter().enumerate() {
+            write!(fmt, "{:1$}", name, name_width)?;
+            for (j, tally) in self.tallies[i].iter().enumerate() {
+                let cell = if i == j { "-".to_string() } else { format!("{}/{}/{}", tally.wins, tally.draws, tally.losses) };
+                write!(fmt, " | {:>1$}", cell, cell_width)?;
+            }
+
+            let total = self.total(i);
+            let cell = format!("{}/{}/{}", total.wins, total.draws, total.losses);
+            if total.games() == 0 {
+                writeln!(fmt, " | {:>11} |", cell)?;
+            } else {
+                let (elo, margin) = total.elo();
+                writeln!(fmt, " | {:>11} | {:+.0} ± {:.0}", cell, elo, margin)?;
+            }
+        }
+
+        writeln!(fmt)?;
+        for (i, name) in self.names.iter().enumerate() {
+            for (j, tally) in self.tallies[i].iter().enumerate().skip(i + 1) {
+                if tally.games() > 0 {
+                    let (elo, margin) = tally.elo();
+                    writeln!(fmt, "{} vs {}: {:+.0} ± {:.0}", name, self.names[j], elo, margin)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elo() {
+        let (elo, margin) = Tally { wins: 10, draws: 0, losses: 10 }.elo();
+        assert_eq!(elo, 0.0);
+        assert!(margin > 0.0 && margin.is_finite());
+
+        // Three points out of four is about 191 Elo.
+        let (elo, _) = Tally { wins: 3, draws: 0, losses: 1 }.elo();
+        assert!((elo - 190.85).abs() < 0.01, "{}", elo);
+        assert_eq!(Tally { wins: 2, draws: 2, losses: 0 }.elo().0, elo);
+
+        // More games make the margin smaller.
+        let (_, few) = Tally { wins: 6, draws: 2, losses: 2 }.elo();
+        let (_, many) = Tally { wins: 60, draws: 20, losses: 20 }.elo();
+        assert!(many < few);
+
+        assert_eq!(Tally { wins: 5, draws: 0, losses: 0 }.elo(), (f64::INFINITY, f64::INFINITY));
+        assert_eq!(Tally { wins: 0, draws: 1, losses: 5 }.elo().1, f64::INFINITY);
+    }
+}
@@ -0,0 +1,78 @@
+use crate::Score;
+use crate::board::Move;
+use std::sync::Mutex;
+
+/// The default number of entries in a `TranspositionTable`.
+pub const DEFAULT_TABLE_SIZE: usize = 1 << 18;
+
+/// How the score of an entry relates to the real score of the position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    /// The score is the real score.
+    Exact,
+    /// The search failed high, the real score is at least this good.
+    Lower,
+    /// The search failed low, the real score is at most this good.
+    Upper,
+}
+
+#[derive(Clone, Copy)]
+pub struct Entry {
+    pub hash: u64,
+    pub depth: u32,
+    pub bound: Bound,
+    pub best_move: Option<Move>,
+    pub score: Score,
+}
+
+/// A fixed size table of previously searched positions, indexed by the zobrist hash of the board. It can be shared
+/// between threads, every slot has its own lock so that workers rarely have to wait on each other.
+pub struct TranspositionTable {
+    slots: Box<[Mutex<Option<Entry>>]>,
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        Self::new(DEFAULT_TABLE_SIZE)
+    }
+}
+
+impl TranspositionTable {
+    pub fn new(size: usize) -> Self {
+        assert!(size > 0, "A transposition table needs at least one slot");
+
+        Self {
+            slots: (0..size).map(|_| Mutex::new(None)).collect(),
+        }
+    }
+
+    /// A table that uses at most half of `bytes`, to leave room for everything else. It's never bigger than
+    /// the default size.
+    pub fn with_memory_limit(bytes: u64) -> Self {
+        let slot_size = std::mem::size_of::<Mutex<Option<Entry>>>() as u64;
+        Self::new((bytes / 2 / slot_size).clamp(1, DEFAULT_TABLE_SIZE as u64) as usize)
+    }
+
+    fn slot(&self, hash: u64) -> &Mutex<Option<Entry>> {
+        &self.slots[(hash % self.slots.len() as u64) as usize]
+    }
+
+    pub fn get(&self, hash: u64) -> Option<Entry> {
+        let entry = (*self.slot(hash).lock().unwrap())?;
+        if entry.hash == hash {
+            Some(entry)
+        } else {
+            None
+        }
+    }
+
+    /// Stores an entry. An entry for the same position is only replaced by an at least as deep search, other
+    /// positions are always replaced.
+    pub fn store(&self, entry: Entry) {
+        let mut slot = self.slot(entry.hash).lock().unwrap();
+        match *slot {
+            Some(old) if old.hash == entry.hash && old.depth > entry.depth => {}
+            _ => *slot = Some(entry),
+        }
+    }
+}
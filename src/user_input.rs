@@ -1,8 +1,81 @@
 use crate::Ai;
-use crate::board::{Board, Move};
+use crate::board::{Board, CoordStyle, Move, Player};
+use crate::opening::Swap2Choice;
 
+fn read_line() -> String {
+    let mut string = String::new();
+    let _ = std::io::stdin().read_line(&mut string);
+    string
+}
+
+/// Asks the user for `count` opening stones, showing the board after every stone.
+fn read_opening(board: &Board, count: usize, style: CoordStyle) -> Option<Vec<Move>> {
+    let mut scratch = board.clone();
+    let mut moves = Vec::with_capacity(count);
+    while moves.len() < count {
+        scratch.print_with_style(style);
+        println!("Enter opening stone {} of {}:", moves.len() + 1, count);
+
+        if let Some(r#move) = read_valid_move(&scratch, &read_line(), style) {
+            scratch.do_move(r#move);
+            moves.push(r#move);
+        }
+    }
+    Some(moves)
+}
+
+/// Reads a move that can be done on the board, explaining to the user why the moves that can't be done were
+/// rejected.
+fn read_valid_move(board: &Board, string: &str, style: CoordStyle) -> Option<Move> {
+    let result = Move::from_string_with_style(board.current_player, string, style, board.height())
+        .and_then(|r#move| board.check_move(r#move).map(|()| r#move));
+    match result {
+        Ok(r#move) => Some(r#move),
+        Err(error) => {
+            println!("Invalid move, {}!", error);
+            None
+        }
+    }
+}
+
+fn parse_color(string: &str) -> Option<Player> {
+    match string.trim() {
+        "x" | "X" => Some(Player::A),
+        "o" | "O" => Some(Player::B),
+        _ => None,
+    }
+}
+
+fn read_color(board: &Board, style: CoordStyle) -> Option<Player> {
+    board.print_with_style(style);
+    loop {
+        println!("Which color do you want to play(x or o)?");
+
+        if let Some(color) = parse_color(&read_line()) {
+            return Some(color);
+        }
+    }
+}
+
+fn read_swap2(board: &Board, style: CoordStyle) -> Option<Swap2Choice> {
+    board.print_with_style(style);
+    loop {
+        println!("Which color do you want to play(x or o), or do you want to place two more stones(p)?");
+
+        let string = read_line();
+        if string.trim() == "p" {
+            return Some(Swap2Choice::PlaceTwo);
+        } else if let Some(color) = parse_color(&string) {
+            return Some(Swap2Choice::Color(color));
+        }
+    }
+}
+
+/// Lets the user play, and asks another `Ai` for a move when the user leaves the move blank. The moves are read
+/// in the given coordinate style.
+#[allow(dead_code)]
 #[derive(Debug, Default, Clone)]
-pub struct UserInputWithHelper<T>(pub String, pub T);
+pub struct UserInputWithHelper<T>(pub String, pub T, pub CoordStyle);
 
 impl<T> Ai for UserInputWithHelper<T> where T: Ai {
     fn requires_user_output(&self) -> bool { true }
@@ -13,26 +86,32 @@ impl<T> Ai for UserInputWithHelper<T> where T: Ai {
         loop {
             println!("Enter the move you want to do(or leave blank for ai help):");
 
-            let mut string = String::new();
-            let _ = std::io::stdin().read_line(&mut string);
+            let string = read_line();
 
             if string.trim().is_empty() {
                 return self.1.pick_move(board);
-            } else {
-                if let Some(r#move) = Move::from_string(board.current_player, &string) {
-                    if board.is_move_valid(r#move) {
-                        return Some(r#move);
-                    } else {
-                        println!("Invalid move!");
-                    }
-                }
+            } else if let Some(r#move) = read_valid_move(board, &string, self.2) {
+                return Some(r#move);
             }
         }
     }
+
+    fn place_opening(&self, board: &mut Board, count: usize) -> Option<Vec<Move>> {
+        read_opening(board, count, self.2)
+    }
+
+    fn choose_color(&self, board: &mut Board) -> Option<Player> {
+        read_color(board, self.2)
+    }
+
+    fn choose_swap2(&self, board: &mut Board) -> Option<Swap2Choice> {
+        read_swap2(board, self.2)
+    }
 }
 
+/// Lets the user play, reading the moves in the given coordinate style.
 #[derive(Debug, Default, Clone)]
-pub struct UserInput(pub String);
+pub struct UserInput(pub String, pub CoordStyle);
 
 impl Ai for UserInput {
     fn requires_user_output(&self) -> bool { true }
@@ -43,16 +122,21 @@ impl Ai for UserInput {
         loop {
             println!("Enter the move you want to do:");
 
-            let mut string = String::new();
-            let _ = std::io::stdin().read_line(&mut string);
-
-            if let Some(r#move) = Move::from_string(board.current_player, &string) {
-                if board.is_move_valid(r#move) {
-                    return Some(r#move);
-                } else {
-                    println!("Invalid move!");
-                }
+            if let Some(r#move) = read_valid_move(board, &read_line(), self.1) {
+                return Some(r#move);
             }
         }
     }
+
+    fn place_opening(&self, board: &mut Board, count: usize) -> Option<Vec<Move>> {
+        read_opening(board, count, self.1)
+    }
+
+    fn choose_color(&self, board: &mut Board) -> Option<Player> {
+        read_color(board, self.1)
+    }
+
+    fn choose_swap2(&self, board: &mut Board) -> Option<Swap2Choice> {
+        read_swap2(board, self.1)
+    }
 }
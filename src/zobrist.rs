@@ -0,0 +1,28 @@
+//! Zobrist hashing of board positions.
+//!
+//! Instead of storing a table of random numbers the keys are generated on the fly by hashing the coordinate with
+//! splitmix64, so that boards of any size can be hashed and the keys are the same between runs.
+
+use crate::board::Player;
+use glam::IVec2;
+
+/// Toggled into the hash on every move, so that the same stones with a different player to move hash differently.
+pub const SIDE_KEY: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// The splitmix64 mixing function, gives a well distributed 64 bit number for any input.
+pub fn splitmix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// The key of a stone of `player` placed at `pos`.
+pub fn tile_key(pos: IVec2, player: Player) -> u64 {
+    let player = match player {
+        Player::A => 0,
+        Player::B => 1,
+    };
+
+    splitmix64(((pos.y as u32 as u64) << 33) | ((pos.x as u32 as u64) << 1) | player)
+}